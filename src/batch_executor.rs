@@ -1,7 +1,12 @@
+use std::ops::ControlFlow;
+
 use async_trait::async_trait;
 use tokio::sync::watch::Receiver;
+use tokio::time::Instant;
 use tracing::info;
 
+use crate::pacing::Pacing;
+
 #[async_trait]
 pub trait Executable: Sized {
     type Result;
@@ -9,16 +14,23 @@ pub trait Executable: Sized {
     async fn execute(self) -> Self::Result;
 }
 
-pub(crate) struct BatchExecutor<E: Executable> {
+/// Runs its tasks one after the other. Tasks are created lazily, while
+/// iterating, so they don't all have to fit in memory.
+pub(crate) struct BatchExecutor<I> {
     id: usize,
-    tasks: Vec<E>
+    tasks: I,
+    pacing: Pacing,
 }
-impl<E: Executable> BatchExecutor<E> {
-    pub(crate) fn new(id: usize, tasks: Vec<E>) -> Self {
-        Self { id, tasks }
+impl<I> BatchExecutor<I>
+where
+    I: Iterator,
+    I::Item: Executable<Result = ControlFlow<()>>,
+{
+    pub(crate) fn new(id: usize, tasks: I, pacing: Pacing) -> Self {
+        Self { id, tasks, pacing }
     }
     pub(crate) async fn start(
-        self,
+        mut self,
         mut start_signal_receiver: Receiver<()>,
     ) {
         info!("starting executor: {}", self.id);
@@ -26,8 +38,22 @@ impl<E: Executable> BatchExecutor<E> {
             .changed()
             .await
             .expect("error receiving start signal");
-        for task in self.tasks {
-            task.execute().await;
+        let mut previous_start: Option<Instant> = None;
+        loop {
+            // the wait comes before taking the next task: a shared task
+            // taken early would be held back from the other executors
+            if let Some(previous_start) = previous_start {
+                tokio::time::sleep(self.pacing.delay(previous_start)).await;
+            }
+            let Some(task) = self.tasks.next() else {
+                break;
+            };
+            let start = Instant::now();
+            if task.execute().await.is_break() {
+                info!("executor {} has nothing left to send", self.id);
+                break;
+            }
+            previous_start = Some(start);
         }
     }
 }
@@ -1,8 +1,10 @@
-use std::{iter::{Skip, StepBy}};
+use std::sync::{Arc, Mutex};
 
-/// Takes an input iterator and return an iterator of iterators in provided batches size
+/// Tasks shared by several executors, each one taking the next task when
+/// it is ready for it. The input iterator is only iterated once, whatever
+/// the number of executors.
 ///
-/// Example : input vector of 5 and split in 3 batches
+/// Example : input vector of 5 shared by 3 executors
 ///
 /// ---------------------
 /// | 1 | 2 | 3 | 4 | 5 |
@@ -10,50 +12,64 @@ use std::{iter::{Skip, StepBy}};
 ///        |
 ///        |
 ///        V
-/// -------------
-/// | 1 | 2 | 3 |         batch 1:   1,4
-/// -------------     ->  batch 2:   2,5
-/// | 4 | 5 |             batch 3:   3
+/// executor 1:   1, then 4 if it is done first
+/// executor 2:   2, then 5 if it is done next
+/// executor 3:   3
 ///
-pub(crate) fn split<I>(iter: (impl Iterator<Item = I> + Clone), n_batches: usize) -> Vec<StepBy<Skip<impl Iterator<Item = I>>>>
+pub(crate) fn share<I>(iter: I, n_executors: usize) -> Vec<SharedTasks<I>>
 where
-    I: Clone,
+    I: Iterator,
 {
-    let mut batches = Vec::with_capacity(n_batches);
-    for i in 0..n_batches {
-        let iter = iter.clone().skip(i).step_by(n_batches);
-        batches.push(iter);
+    let shared = SharedTasks(Arc::new(Mutex::new(iter)));
+    (0..n_executors).map(|_| shared.clone()).collect()
+}
+
+pub(crate) struct SharedTasks<I>(Arc<Mutex<I>>);
+
+impl<I> Clone for SharedTasks<I> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<I> Iterator for SharedTasks<I>
+where
+    I: Iterator,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.lock().expect("task iterator poisoned").next()
     }
-    batches
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::batcher::split;
+    use crate::batcher::share;
 
     #[test]
-    fn it_should_return_() {
+    fn it_should_hand_out_each_task_once() {
         let input = vec![1, 2, 3, 4, 5];
-        let mut splits = split(input.into_iter(), 3);
+        let mut executors = share(input.into_iter(), 3);
 
-        let batch2: Vec<i32> = splits.pop().unwrap().collect();
-        let batch1: Vec<i32> = splits.pop().unwrap().collect();
-        let batch0: Vec<i32> = splits.pop().unwrap().collect();
+        let first: Vec<i32> = executors[0].by_ref().take(2).collect();
+        let second: Vec<i32> = executors[1].by_ref().collect();
+        let third: Vec<i32> = executors[2].by_ref().collect();
 
-        assert_eq!(vec![1, 4], batch0);
-        assert_eq!(vec![2, 5], batch1);
-        assert_eq!(vec![3], batch2);
+        assert_eq!(vec![1, 2], first);
+        assert_eq!(vec![3, 4, 5], second);
+        assert!(third.is_empty());
     }
 
     #[test]
-    fn it_should_run_2() {
-        let input = vec![1, 2, 3, 4];
-        let mut splits = split(input.into_iter(), 2);
+    fn it_should_iterate_the_input_once() {
+        let mut iterated = 0;
+        let input = (0..4).inspect(|_| iterated += 1);
+        let executors = share(input, 2);
 
-        let batch1: Vec<i32> = splits.pop().unwrap().collect();
-        let batch0: Vec<i32> = splits.pop().unwrap().collect();
+        let count: usize = executors.into_iter().map(|tasks| tasks.count()).sum();
 
-        assert_eq!(vec![1, 3], batch0);
-        assert_eq!(vec![2, 4], batch1);
+        assert_eq!(4, count);
+        assert_eq!(4, iterated);
     }
 }
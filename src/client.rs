@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::fs;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use reqwest::{Certificate, Identity};
+
+use crate::config::TlsParameters;
+
+const PEM_CERTIFICATE_BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+
+/// Http clients shared by the login and all the executors.
+///
+/// The connect timeout can only be set on the client, so an extra client is
+/// created for each distinct per-request connect timeout. Creating a client
+/// is an expensive task, so it must be done only once.
+#[derive(Clone)]
+pub(crate) struct HttpClients {
+    default: reqwest::Client,
+    default_connect_timeout: Option<Duration>,
+    by_connect_timeout: HashMap<Duration, reqwest::Client>,
+}
+
+impl HttpClients {
+    pub(crate) fn new(
+        tls: &TlsParameters,
+        connect_timeout: Option<Duration>,
+        request_timeout: Option<Duration>,
+        request_connect_timeouts: impl Iterator<Item = Duration>,
+    ) -> anyhow::Result<Self> {
+        let default = build_client(tls, connect_timeout, request_timeout)?;
+        let mut by_connect_timeout = HashMap::new();
+        for timeout in request_connect_timeouts {
+            if Some(timeout) != connect_timeout && !by_connect_timeout.contains_key(&timeout) {
+                let client = build_client(tls, Some(timeout), request_timeout)?;
+                by_connect_timeout.insert(timeout, client);
+            }
+        }
+        Ok(Self {
+            default,
+            default_connect_timeout: connect_timeout,
+            by_connect_timeout,
+        })
+    }
+
+    pub(crate) fn default_client(&self) -> &reqwest::Client {
+        &self.default
+    }
+
+    pub(crate) fn for_connect_timeout(&self, connect_timeout: Option<Duration>) -> &reqwest::Client {
+        match connect_timeout {
+            Some(timeout) if connect_timeout != self.default_connect_timeout => self
+                .by_connect_timeout
+                .get(&timeout)
+                .expect("client created for every request connect timeout"),
+            _ => &self.default,
+        }
+    }
+}
+
+/// `request_timeout` is the default for the client (the login included),
+/// each request can still override it.
+fn build_client(
+    tls: &TlsParameters,
+    connect_timeout: Option<Duration>,
+    request_timeout: Option<Duration>,
+) -> anyhow::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder();
+    if let Some(connect_timeout) = connect_timeout {
+        builder = builder.connect_timeout(connect_timeout);
+    }
+    if let Some(request_timeout) = request_timeout {
+        builder = builder.timeout(request_timeout);
+    }
+
+    if let Some(identity) = load_identity(tls)? {
+        builder = builder.identity(identity);
+    }
+    if let Some(ca_bundle) = &tls.ca_bundle {
+        let pem = fs::read_to_string(ca_bundle)
+            .with_context(|| format!("error reading ca bundle {:?}", ca_bundle))?;
+        let certificates = split_pem_certificates(&pem);
+        if certificates.is_empty() {
+            bail!("no certificates found in ca bundle {:?}", ca_bundle);
+        }
+        for certificate in certificates {
+            let certificate = Certificate::from_pem(certificate.as_bytes())
+                .with_context(|| format!("invalid certificate in ca bundle {:?}", ca_bundle))?;
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    if tls.insecure_skip_verify {
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+
+    builder.build().context("error creating http client")
+}
+
+fn load_identity(tls: &TlsParameters) -> anyhow::Result<Option<Identity>> {
+    match (&tls.client_cert, &tls.client_key, &tls.client_pkcs12) {
+        (None, None, None) => Ok(None),
+        (Some(cert), Some(key), None) => {
+            let cert = fs::read(cert)
+                .with_context(|| format!("error reading client certificate {:?}", cert))?;
+            let key =
+                fs::read(key).with_context(|| format!("error reading client key {:?}", key))?;
+            let identity =
+                Identity::from_pkcs8_pem(&cert, &key).context("invalid client certificate/key")?;
+            Ok(Some(identity))
+        }
+        (None, None, Some(pkcs12)) => {
+            let der = fs::read(pkcs12)
+                .with_context(|| format!("error reading pkcs12 bundle {:?}", pkcs12))?;
+            let password = tls.client_pkcs12_password.as_deref().unwrap_or_default();
+            let identity =
+                Identity::from_pkcs12_der(&der, password).context("invalid pkcs12 bundle")?;
+            Ok(Some(identity))
+        }
+        (Some(_), None, None) | (None, Some(_), None) => {
+            bail!("tls: client_cert and client_key must be provided together")
+        }
+        _ => bail!("tls: use either client_cert/client_key or client_pkcs12, not both"),
+    }
+}
+
+/// A CA bundle usually holds several certificates, but `Certificate::from_pem`
+/// only reads the first one, so the bundle is split beforehand.
+fn split_pem_certificates(pem: &str) -> Vec<String> {
+    pem.split(PEM_CERTIFICATE_BEGIN)
+        .skip(1)
+        .map(|body| format!("{}{}", PEM_CERTIFICATE_BEGIN, body.trim_end()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_pem_certificates;
+
+    #[test]
+    fn it_should_split_a_bundle_in_certificates() {
+        let bundle = "# comment\n-----BEGIN CERTIFICATE-----\nAAA\n-----END CERTIFICATE-----\n\
+                      -----BEGIN CERTIFICATE-----\nBBB\n-----END CERTIFICATE-----\n";
+        let certificates = split_pem_certificates(bundle);
+
+        assert_eq!(2, certificates.len());
+        assert_eq!(
+            "-----BEGIN CERTIFICATE-----\nAAA\n-----END CERTIFICATE-----",
+            certificates[0]
+        );
+        assert!(certificates[1].contains("BBB"));
+    }
+
+    #[test]
+    fn it_should_return_empty_without_certificates() {
+        assert!(split_pem_certificates("not a pem").is_empty());
+    }
+}
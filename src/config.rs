@@ -1,17 +1,172 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context};
 use confique::Config;
+use rand::distributions::WeightedIndex;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use serde_json_path::JsonPath;
+
+use crate::interpolation;
+use crate::request::ErrorClass;
+
+/// HTTP methods accepted as a request `action`
+pub const ACTIONS: [&str; 5] = ["POST", "PUT", "GET", "PATCH", "DELETE"];
 
 #[derive(Debug, Config)]
 pub struct Configuration {
     #[config(nested)]
     pub login: LoginParameters,
+    #[config(nested)]
+    pub tls: TlsParameters,
     #[config(default = 1)]
     pub concurrect_requests: usize,
+    /// Rounds over all the `requests`, `scenarios` and requests file lines
     #[config(default = 1)]
     pub iterations: usize,
+    /// Default timeout to establish connections, e.g. "5s"
+    #[config(deserialize_with = humantime_serde::deserialize)]
+    pub connect_timeout: Option<Duration>,
+    /// Default timeout for the whole request, e.g. "30s"
+    #[config(deserialize_with = humantime_serde::deserialize)]
+    pub request_timeout: Option<Duration>,
+    /// Retry policy for every request without its own `retry` entry
+    pub retry: Option<RetryParameters>,
+    /// Delay between the tasks (requests or scenario runs) of an executor
+    pub think_time: Option<ThinkTime>,
+    /// Fixed interval between the starts of the tasks of an executor, e.g. "2s"
+    #[config(deserialize_with = humantime_serde::deserialize)]
+    pub pacing: Option<Duration>,
+    /// Requests sent first and left out of the statistics: a duration from
+    /// the first request ("30s") or a number of requests (100)
+    pub warmup: Option<Warmup>,
+    /// Seed of every random value (request mix, templates, feeders, retry
+    /// jitter) so a run can be reproduced, random when missing
+    pub seed: Option<u64>,
+    /// OpenTelemetry collector receiving the request spans and metrics
+    pub otlp: Option<OtlpParameters>,
+    /// W3C `traceparent` (and `baggage`) headers added to every request
+    pub trace_context: Option<TraceContextParameters>,
+    /// InfluxDB and StatsD servers receiving the completed requests
+    #[config(default=[])]
+    pub metric_sinks: Vec<MetricSinkParameters>,
+    /// Signing applied to every request without its own `signing` entry
+    pub signing: Option<SigningParameters>,
     #[config(default=[])]
     pub requests: Vec<RequestParameters>,
+    /// JSONL file with one more request per line, streamed during the run
+    pub requests_file: Option<PathBuf>,
+    /// Ordered steps run sequentially by a virtual user, sharing extracted variables
+    #[config(default=[])]
+    pub scenarios: Vec<ScenarioParameters>,
+    /// CSV/JSONL files whose rows are bound to template variables
+    #[config(default=[])]
+    pub feeders: Vec<FeederParameters>,
+}
+
+impl Configuration {
+    /// Loads the (yaml) configuration file resolving environment variables
+    /// and secret files referenced in its string fields.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("error reading {:?}", path))?;
+        let mut value: serde_yaml::Value =
+            serde_yaml::from_str(&content).with_context(|| format!("error parsing {:?}", path))?;
+        interpolation::interpolate_value(&mut value)?;
+        let partial: <Self as Config>::Partial = serde_yaml::from_value(value)
+            .with_context(|| format!("invalid configuration in {:?}", path))?;
+        let conf = Self::builder()
+            .preloaded(partial)
+            .load()
+            .with_context(|| format!("invalid configuration in {:?}", path))?;
+        for scenario in &conf.scenarios {
+            if let Some(feeder) = &scenario.feeder {
+                conf.check_feeder(feeder)
+                    .with_context(|| format!("in scenario '{}'", scenario.name))?;
+            }
+        }
+        if let Some(retry) = &conf.retry {
+            retry.validate()?;
+        }
+        for req in conf.all_requests() {
+            conf.validate_request(req)?;
+        }
+        if let Some(ThinkTime::Uniform { min, max }) = conf.think_time {
+            if min > max {
+                bail!("think_time: min {:?} is greater than max {:?}", min, max);
+            }
+        }
+        if let Some(weights) = conf.weights() {
+            WeightedIndex::new(weights).context("invalid request and scenario weights")?;
+        }
+        Ok(conf)
+    }
+
+    /// Checks what can't be checked while deserializing the request
+    pub fn validate_request(&self, req: &RequestParameters) -> anyhow::Result<()> {
+        if !ACTIONS.contains(&req.action.as_str()) {
+            bail!(
+                "unsupported action '{}' in request '{}', expected one of {}",
+                req.action,
+                req.display_name(),
+                ACTIONS.join(", ")
+            );
+        }
+        if let Some(feeder) = &req.feeder {
+            self.check_feeder(feeder)
+                .with_context(|| format!("in request '{}'", req.display_name()))?;
+        }
+        if let Some(retry) = &req.retry {
+            retry
+                .validate()
+                .with_context(|| format!("in request '{}'", req.display_name()))?;
+        }
+        Ok(())
+    }
+
+    fn check_feeder(&self, name: &str) -> anyhow::Result<()> {
+        if !self.feeders.iter().any(|feeder| feeder.name == name) {
+            bail!("unknown feeder '{}'", name);
+        }
+        Ok(())
+    }
+
+    /// Fills the request options not set in the request with the global ones
+    pub fn with_defaults(&self, mut req: RequestParameters) -> RequestParameters {
+        if req.signing.is_none() {
+            req.signing = self.signing.clone();
+        }
+        req.request_timeout = req.request_timeout.or(self.request_timeout);
+        if req.retry.is_none() {
+            req.retry = self.retry.clone();
+        }
+        req
+    }
+
+    /// Weights of the `requests` then `scenarios` entries, 1 when missing.
+    /// `None` when no weight is set: entries are then taken in turn.
+    pub fn weights(&self) -> Option<Vec<u32>> {
+        let weights = self
+            .requests
+            .iter()
+            .map(|req| req.weight)
+            .chain(self.scenarios.iter().map(|scenario| scenario.weight));
+        if weights.clone().all(|weight| weight.is_none()) {
+            return None;
+        }
+        Some(weights.map(|weight| weight.unwrap_or(1)).collect())
+    }
+
+    /// All the requests that may be sent, scenario steps included
+    pub fn all_requests(&self) -> impl Iterator<Item = &RequestParameters> {
+        self.requests
+            .iter()
+            .chain(self.scenarios.iter().flat_map(|scenario| scenario.steps.iter()))
+    }
 }
 
 #[derive(Debug, Config)]
@@ -24,10 +179,322 @@ pub struct LoginParameters {
     pub grant_type: String,
 }
 
+#[derive(Debug, Config)]
+pub struct TlsParameters {
+    /// PEM client certificate, used together with `client_key`
+    pub client_cert: Option<PathBuf>,
+    /// PEM (PKCS#8) private key for `client_cert`
+    pub client_key: Option<PathBuf>,
+    /// PKCS#12 bundle holding both client certificate and key
+    pub client_pkcs12: Option<PathBuf>,
+    pub client_pkcs12_password: Option<String>,
+    /// Extra PEM CA certificates trusted on top of the system ones
+    pub ca_bundle: Option<PathBuf>,
+    /// Accept any server certificate (self-signed staging only!)
+    #[config(default = false)]
+    pub insecure_skip_verify: bool,
+}
+
 #[derive(Debug, Clone, Config, Serialize, Deserialize)]
 pub struct RequestParameters {
+    /// Name shown in the metrics, "ACTION url" when missing
+    pub name: Option<String>,
+    /// Share of the `requests` entries in the mix, see [`Configuration::weights`]
+    pub weight: Option<u32>,
     pub url: String,
     pub action: String,
     pub data: Option<Value>,
     pub status_code: Option<u16>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub signing: Option<SigningParameters>,
+    #[serde(default, with = "humantime_serde")]
+    pub connect_timeout: Option<Duration>,
+    #[serde(default, with = "humantime_serde")]
+    pub request_timeout: Option<Duration>,
+    pub retry: Option<RetryParameters>,
+    /// Values taken from the response, available to the following scenario steps
+    #[serde(default)]
+    pub extract: Vec<Extraction>,
+    /// Name of the feeder providing a row of variables for each request
+    pub feeder: Option<String>,
+}
+
+impl RequestParameters {
+    pub fn display_name(&self) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| format!("{} {}", self.action, self.url))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScenarioParameters {
+    pub name: String,
+    /// Share of the scenario runs in the mix, see [`Configuration::weights`]
+    pub weight: Option<u32>,
+    /// Feeder providing a row of variables for each scenario run
+    pub feeder: Option<String>,
+    pub steps: Vec<RequestParameters>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeederParameters {
+    pub name: String,
+    pub path: PathBuf,
+    /// Taken from the file extension when missing
+    pub format: Option<FeederFormat>,
+    #[serde(default)]
+    pub strategy: FeederStrategy,
+    #[serde(default)]
+    pub on_exhausted: OnExhausted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeederFormat {
+    /// With a header line naming the variables
+    Csv,
+    /// One json object per line
+    Jsonl,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeederStrategy {
+    /// Rows in file order, shared by all the virtual users
+    #[default]
+    Sequential,
+    /// Any row, every time
+    Random,
+    /// Each virtual user gets its own rows, never used by the others
+    Unique,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnExhausted {
+    /// Start again from the first row
+    #[default]
+    Wrap,
+    /// Stop sending: the virtual user ends its remaining tasks without sending them
+    Stop,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Extraction {
+    /// Variable name, used as `{{ var }}` in url, headers and data
+    pub var: String,
+    #[serde(flatten)]
+    pub source: ExtractionSource,
+}
+
+/// The expressions are compiled when loading, so a typo fails then rather
+/// than mid-run, and the responses don't compile them again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtractionSource {
+    /// JSONPath over the response body, e.g. `$.order.id`
+    #[serde(with = "crate::extract::compiled_json_path")]
+    JsonPath(JsonPath),
+    /// Response header name
+    Header(String),
+    /// Regex over the response body, the first capture group if there is one
+    #[serde(with = "crate::extract::compiled_regex")]
+    Regex(Regex),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryParameters {
+    /// Total attempts, the first one included
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: usize,
+    /// Response status codes to retry (even when no `status_code` is expected)
+    #[serde(default = "default_retry_status_codes")]
+    pub status_codes: Vec<u16>,
+    /// Error classes to retry
+    #[serde(default = "default_retry_error_classes")]
+    pub error_classes: Vec<ErrorClass>,
+    #[serde(default = "default_initial_backoff", with = "humantime_serde")]
+    pub initial_backoff: Duration,
+    #[serde(default = "default_max_backoff", with = "humantime_serde")]
+    pub max_backoff: Duration,
+    #[serde(default = "default_backoff_multiplier")]
+    pub multiplier: f64,
+    /// Waits a random time between zero and the computed backoff ("full jitter")
+    #[serde(default = "default_true")]
+    pub jitter: bool,
+    /// Waits what the server says in `Retry-After` on 429 and 503 responses,
+    /// up to `max_backoff`
+    #[serde(default = "default_true")]
+    pub respect_retry_after: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Warmup {
+    Requests(usize),
+    Duration(#[serde(with = "humantime_serde")] Duration),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OtlpParameters {
+    /// e.g. "http://localhost:4317" with grpc, "http://localhost:4318" with http
+    pub endpoint: String,
+    #[serde(default)]
+    pub protocol: OtlpProtocol,
+    /// Interval between two exports of the metrics
+    #[serde(default = "default_otlp_export_interval", with = "humantime_serde")]
+    pub export_interval: Duration,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricSinkParameters {
+    #[serde(flatten)]
+    pub kind: MetricSinkKind,
+    /// Added to every metric, next to `run_id`, `name`, `status` and `error_class`
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    /// Metrics are sent in batches, at most this long after the request completed
+    #[serde(default = "default_flush_interval", with = "humantime_serde")]
+    pub flush_interval: Duration,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MetricSinkKind {
+    /// Line protocol posted to a write endpoint, e.g.
+    /// "http://localhost:8086/api/v2/write?org=my-org&bucket=parreq&precision=ns"
+    InfluxdbHttp {
+        url: String,
+        /// Sent as `Authorization: Token <token>`
+        token: Option<String>,
+    },
+    /// Line protocol over udp, e.g. "127.0.0.1:8089"
+    InfluxdbUdp { address: String },
+    /// StatsD over udp with DogStatsD tags, e.g. "127.0.0.1:8125"
+    Statsd {
+        address: String,
+        #[serde(default = "default_statsd_prefix")]
+        prefix: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceContextParameters {
+    /// Also sends `baggage` with the run id and the request number
+    #[serde(default)]
+    pub baggage: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OtlpProtocol {
+    #[default]
+    Grpc,
+    /// Protobuf over http
+    Http,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ThinkTime {
+    /// Always the same delay
+    Fixed {
+        #[serde(with = "humantime_serde")]
+        duration: Duration,
+    },
+    /// Any delay between `min` and `max`, all equally likely
+    Uniform {
+        #[serde(with = "humantime_serde")]
+        min: Duration,
+        #[serde(with = "humantime_serde")]
+        max: Duration,
+    },
+    /// Exponentially distributed delay with the given mean, short delays
+    /// being the most likely
+    Exponential {
+        #[serde(with = "humantime_serde")]
+        mean: Duration,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SigningParameters {
+    /// HMAC-SHA256 over method, path, timestamp and body
+    Hmac(HmacSigningParameters),
+    /// AWS Signature Version 4 (API Gateway with IAM auth)
+    AwsSigv4(AwsSigningParameters),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HmacSigningParameters {
+    pub secret: String,
+    #[serde(default = "default_signature_header")]
+    pub signature_header: String,
+    #[serde(default = "default_timestamp_header")]
+    pub timestamp_header: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AwsSigningParameters {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+    pub region: String,
+    #[serde(default = "default_aws_service")]
+    pub service: String,
+}
+
+fn default_max_attempts() -> usize {
+    3
+}
+
+fn default_retry_status_codes() -> Vec<u16> {
+    vec![429, 502, 503, 504]
+}
+
+fn default_retry_error_classes() -> Vec<ErrorClass> {
+    vec![ErrorClass::Timeout, ErrorClass::Connect]
+}
+
+fn default_initial_backoff() -> Duration {
+    Duration::from_millis(100)
+}
+
+fn default_max_backoff() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_backoff_multiplier() -> f64 {
+    2.0
+}
+
+fn default_otlp_export_interval() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_flush_interval() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_statsd_prefix() -> String {
+    "parreq".to_owned()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_signature_header() -> String {
+    "X-Signature".to_owned()
+}
+
+fn default_timestamp_header() -> String {
+    "X-Timestamp".to_owned()
+}
+
+fn default_aws_service() -> String {
+    "execute-api".to_owned()
 }
@@ -0,0 +1,156 @@
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::config::{Extraction, ExtractionSource};
+use crate::request::{ErrorClass, RequestError};
+use crate::template::Variables;
+
+impl fmt::Display for ExtractionSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExtractionSource::JsonPath(path) => write!(f, "json_path '{}'", path),
+            ExtractionSource::Header(name) => write!(f, "header '{}'", name),
+            ExtractionSource::Regex(regex) => write!(f, "regex '{}'", regex),
+        }
+    }
+}
+
+/// (De)serializes a JSONPath as its text, parsed when deserialized
+pub(crate) mod compiled_json_path {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use serde_json_path::JsonPath;
+
+    pub(crate) fn serialize<S: Serializer>(
+        path: &JsonPath,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(path)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<JsonPath, D::Error> {
+        let path = String::deserialize(deserializer)?;
+        JsonPath::parse(&path)
+            .map_err(|e| serde::de::Error::custom(format!("invalid json_path '{}': {}", path, e)))
+    }
+}
+
+/// (De)serializes a regex as its pattern, compiled when deserialized
+pub(crate) mod compiled_regex {
+    use regex::Regex;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(crate) fn serialize<S: Serializer>(
+        regex: &Regex,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(regex.as_str())
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Regex, D::Error> {
+        let regex = String::deserialize(deserializer)?;
+        Regex::new(&regex)
+            .map_err(|e| serde::de::Error::custom(format!("invalid regex '{}': {}", regex, e)))
+    }
+}
+
+/// Reads the response (the body only when needed) into the extracted variables
+pub(crate) async fn extract(
+    resp: reqwest::Response,
+    extractions: &[Extraction],
+) -> Result<Variables, RequestError> {
+    let mut variables = Variables::new();
+    if extractions.is_empty() {
+        return Ok(variables);
+    }
+    let headers = resp.headers().clone();
+    let needs_body = extractions
+        .iter()
+        .any(|e| !matches!(e.source, ExtractionSource::Header(_)));
+    let body = if needs_body {
+        resp.text().await?
+    } else {
+        String::new()
+    };
+    let mut json: Option<Value> = None;
+
+    for extraction in extractions {
+        let value = match &extraction.source {
+            ExtractionSource::Header(name) => headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned),
+            ExtractionSource::JsonPath(path) => {
+                if json.is_none() {
+                    json = Some(serde_json::from_str(&body).map_err(|e| {
+                        extraction_error(format!("response body is not json: {}", e))
+                    })?);
+                }
+                path.query(json.as_ref().expect("json parsed above"))
+                    .first()
+                    .map(json_to_string)
+            }
+            ExtractionSource::Regex(regex) => regex.captures(&body).map(|captures| {
+                captures
+                    .get(1)
+                    .or_else(|| captures.get(0))
+                    .map_or_else(String::new, |m| m.as_str().to_owned())
+            }),
+        };
+        let value = value.ok_or_else(|| {
+            extraction_error(format!(
+                "no value found for '{}' ({})",
+                extraction.var, extraction.source
+            ))
+        })?;
+        variables.insert(extraction.var.clone(), value);
+    }
+    Ok(variables)
+}
+
+/// Strings are used as they are, anything else as its json representation
+pub(crate) fn json_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn extraction_error(msg: String) -> RequestError {
+    RequestError::new(ErrorClass::Extraction, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{Extraction, ExtractionSource};
+
+    #[test]
+    fn it_should_compile_the_expressions_when_loading() {
+        let extraction: Extraction =
+            serde_json::from_str(r#"{"var": "id", "regex": "id=(\\d+)"}"#).unwrap();
+
+        let ExtractionSource::Regex(regex) = &extraction.source else {
+            panic!("not a regex: {}", extraction.source);
+        };
+        assert_eq!("42", &regex.captures("id=42").unwrap()[1]);
+        assert_eq!(
+            r#"{"var":"id","regex":"id=(\\d+)"}"#,
+            serde_json::to_string(&extraction).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_should_reject_an_invalid_expression_when_loading() {
+        let json_path = serde_json::from_str::<Extraction>(r#"{"var": "id", "json_path": "$["}"#);
+        let regex = serde_json::from_str::<Extraction>(r#"{"var": "id", "regex": "("}"#);
+
+        let error = json_path.unwrap_err().to_string();
+        assert!(error.contains("invalid json_path '$['"), "{}", error);
+        let error = regex.unwrap_err().to_string();
+        assert!(error.contains("invalid regex '('"), "{}", error);
+    }
+}
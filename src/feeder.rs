@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use anyhow::{bail, Context};
+use rand::Rng;
+use serde_json::Value;
+
+use crate::config::{FeederFormat, FeederParameters, FeederStrategy, OnExhausted};
+use crate::extract::json_to_string;
+use crate::template::Variables;
+
+/// Feeders by name
+pub(crate) type Feeders = HashMap<String, Arc<Feeder>>;
+
+/// Rows of variables loaded from a CSV or JSONL file, handed out to the
+/// requests according to the configured strategy.
+#[derive(Debug)]
+pub(crate) struct Feeder {
+    rows: Vec<Variables>,
+    strategy: FeederStrategy,
+    on_exhausted: OnExhausted,
+    /// Next row for the sequential strategy
+    cursor: AtomicUsize,
+    /// Rows already taken by each virtual user for the unique strategy
+    taken_by_executor: Vec<AtomicUsize>,
+}
+
+pub(crate) fn load_feeders(
+    params: &[FeederParameters],
+    n_executors: usize,
+) -> anyhow::Result<Feeders> {
+    params
+        .iter()
+        .map(|params| {
+            let feeder = Feeder::load(params, n_executors)
+                .with_context(|| format!("error loading feeder '{}'", params.name))?;
+            Ok((params.name.clone(), Arc::new(feeder)))
+        })
+        .collect()
+}
+
+impl Feeder {
+    fn load(params: &FeederParameters, n_executors: usize) -> anyhow::Result<Self> {
+        let format = match params.format {
+            Some(format) => format,
+            None => format_from_extension(&params.path)?,
+        };
+        let file =
+            File::open(&params.path).with_context(|| format!("error opening {:?}", params.path))?;
+        let rows = match format {
+            FeederFormat::Csv => read_csv(file)?,
+            FeederFormat::Jsonl => read_jsonl(file)?,
+        };
+        if rows.is_empty() {
+            bail!("{:?} has no rows", params.path);
+        }
+        // each virtual user needs at least one row of its own
+        if params.strategy == FeederStrategy::Unique && rows.len() < n_executors {
+            bail!(
+                "{:?} has {} rows, the unique strategy needs one per virtual user ({})",
+                params.path,
+                rows.len(),
+                n_executors
+            );
+        }
+        Ok(Self::new(
+            rows,
+            params.strategy,
+            params.on_exhausted,
+            n_executors,
+        ))
+    }
+
+    fn new(
+        rows: Vec<Variables>,
+        strategy: FeederStrategy,
+        on_exhausted: OnExhausted,
+        n_executors: usize,
+    ) -> Self {
+        Self {
+            rows,
+            strategy,
+            on_exhausted,
+            cursor: AtomicUsize::new(0),
+            taken_by_executor: (0..n_executors.max(1))
+                .map(|_| AtomicUsize::new(0))
+                .collect(),
+        }
+    }
+
+    /// Adds the next row to `variables`, `false` once exhausted when
+    /// configured to stop: the virtual user then has nothing left to send
+    pub(crate) fn feed(
+        &self,
+        executor: usize,
+        variables: &mut Variables,
+        rng: &mut impl Rng,
+    ) -> bool {
+        let Some(row) = self.next(executor, rng) else {
+            return false;
+        };
+        variables.extend(row.iter().map(|(k, v)| (k.clone(), v.clone())));
+        true
+    }
+
+    /// Next row for the virtual user `executor`, `None` once exhausted when
+    /// configured to stop
+    fn next(&self, executor: usize, rng: &mut impl Rng) -> Option<&Variables> {
+        let len = self.rows.len();
+        let index = match self.strategy {
+            FeederStrategy::Random => rng.gen_range(0..len),
+            FeederStrategy::Sequential => {
+                let taken = self.cursor.fetch_add(1, Ordering::Relaxed);
+                self.wrap(taken, len)?
+            }
+            FeederStrategy::Unique => {
+                // rows executor, executor + n, executor + 2n...
+                let n = self.taken_by_executor.len();
+                let executor = executor % n;
+                let own_rows = (len + n - 1 - executor) / n;
+                let taken = self.taken_by_executor[executor].fetch_add(1, Ordering::Relaxed);
+                executor + self.wrap(taken, own_rows)? * n
+            }
+        };
+        self.rows.get(index)
+    }
+
+    fn wrap(&self, taken: usize, available: usize) -> Option<usize> {
+        match self.on_exhausted {
+            _ if available == 0 => None,
+            OnExhausted::Wrap => Some(taken % available),
+            OnExhausted::Stop if taken < available => Some(taken),
+            OnExhausted::Stop => None,
+        }
+    }
+}
+
+fn format_from_extension(path: &Path) -> anyhow::Result<FeederFormat> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("csv") => Ok(FeederFormat::Csv),
+        Some("jsonl") | Some("ndjson") => Ok(FeederFormat::Jsonl),
+        _ => bail!("unknown format for {:?}, set `format: csv|jsonl`", path),
+    }
+}
+
+fn read_csv(file: File) -> anyhow::Result<Vec<Variables>> {
+    let mut reader = csv::Reader::from_reader(file);
+    let headers = reader.headers()?.clone();
+    reader
+        .records()
+        .map(|record| {
+            let record = record?;
+            Ok(headers
+                .iter()
+                .zip(record.iter())
+                .map(|(name, value)| (name.to_owned(), value.to_owned()))
+                .collect())
+        })
+        .collect()
+}
+
+fn read_jsonl(file: File) -> anyhow::Result<Vec<Variables>> {
+    let mut rows = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let row: Value = serde_json::from_str(&line)
+            .with_context(|| format!("invalid json at line {}", number + 1))?;
+        let Value::Object(row) = row else {
+            bail!("line {} is not a json object", number + 1);
+        };
+        rows.push(
+            row.iter()
+                .map(|(name, value)| (name.clone(), json_to_string(value)))
+                .collect(),
+        );
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::mock::StepRng;
+
+    use super::Feeder;
+    use crate::config::{FeederParameters, FeederStrategy, OnExhausted};
+    use crate::template::Variables;
+
+    fn feeder(strategy: FeederStrategy, on_exhausted: OnExhausted) -> Feeder {
+        let rows = (0..5)
+            .map(|i| Variables::from([("id".to_owned(), i.to_string())]))
+            .collect();
+        Feeder::new(rows, strategy, on_exhausted, 2)
+    }
+
+    fn ids(feeder: &Feeder, executor: usize, n: usize) -> Vec<Option<String>> {
+        let mut rng = StepRng::new(0, 1);
+        (0..n)
+            .map(|_| feeder.next(executor, &mut rng).map(|row| row["id"].clone()))
+            .collect()
+    }
+
+    #[test]
+    fn it_should_wrap_sequential_rows() {
+        let feeder = feeder(FeederStrategy::Sequential, OnExhausted::Wrap);
+
+        let ids: Vec<_> = ids(&feeder, 0, 7).into_iter().flatten().collect();
+
+        assert_eq!(vec!["0", "1", "2", "3", "4", "0", "1"], ids);
+    }
+
+    #[test]
+    fn it_should_split_unique_rows_by_executor_and_stop() {
+        let feeder = feeder(FeederStrategy::Unique, OnExhausted::Stop);
+
+        let executor0: Vec<_> = ids(&feeder, 0, 4);
+        let executor1: Vec<_> = ids(&feeder, 1, 3);
+
+        assert_eq!(
+            vec![
+                Some("0".to_owned()),
+                Some("2".to_owned()),
+                Some("4".to_owned()),
+                None
+            ],
+            executor0
+        );
+        assert_eq!(
+            vec![Some("1".to_owned()), Some("3".to_owned()), None],
+            executor1
+        );
+    }
+
+    #[test]
+    fn it_should_reject_fewer_unique_rows_than_virtual_users() {
+        let path = std::env::temp_dir().join(format!("parreq-feeder-{}.csv", std::process::id()));
+        std::fs::write(&path, "id\n1\n2\n").unwrap();
+        let params = FeederParameters {
+            name: "ids".to_owned(),
+            path: path.clone(),
+            format: None,
+            strategy: FeederStrategy::Unique,
+            on_exhausted: OnExhausted::Stop,
+        };
+
+        assert!(Feeder::load(&params, 2).is_ok());
+        assert!(Feeder::load(&params, 3).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
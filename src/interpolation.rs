@@ -0,0 +1,151 @@
+use std::{env, fs};
+
+use anyhow::{anyhow, bail, Context};
+use serde_yaml::Value;
+
+const FILE_PREFIX: &str = "file:";
+const DEFAULT_SEPARATOR: &str = ":-";
+
+/// Reads an environment variable, the tests use their own variables
+type Lookup<'a> = &'a dyn Fn(&str) -> Result<String, env::VarError>;
+
+/// Resolves `${ENV_VAR}`, `${ENV_VAR:-default}` and `${file:/path/to/secret}`
+/// in every string of the configuration tree. `$${` is kept as a literal `${`.
+pub(crate) fn interpolate_value(value: &mut Value) -> anyhow::Result<()> {
+    interpolate_value_at(value, &mut Vec::new(), &|name| env::var(name))
+}
+
+fn interpolate_value_at(
+    value: &mut Value,
+    path: &mut Vec<String>,
+    lookup: Lookup,
+) -> anyhow::Result<()> {
+    match value {
+        Value::String(s) => {
+            *s = interpolate(s, lookup)
+                .with_context(|| format!("in configuration field '{}'", path.join(".")))?;
+        }
+        Value::Sequence(seq) => {
+            for (i, item) in seq.iter_mut().enumerate() {
+                path.push(i.to_string());
+                interpolate_value_at(item, path, lookup)?;
+                path.pop();
+            }
+        }
+        Value::Mapping(map) => {
+            for (key, item) in map.iter_mut() {
+                path.push(key.as_str().unwrap_or("?").to_owned());
+                interpolate_value_at(item, path, lookup)?;
+                path.pop();
+            }
+        }
+        Value::Tagged(tagged) => interpolate_value_at(&mut tagged.value, path, lookup)?,
+        Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+    Ok(())
+}
+
+fn interpolate(input: &str, lookup: Lookup) -> anyhow::Result<String> {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find('$') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if rest.starts_with("$${") {
+            output.push_str("${");
+            rest = &rest[3..];
+        } else if let Some(expr) = rest.strip_prefix("${") {
+            let end = expr
+                .find('}')
+                .ok_or_else(|| anyhow!("unterminated '${{' in \"{}\"", input))?;
+            output.push_str(&resolve(&expr[..end], lookup)?);
+            rest = &expr[end + 1..];
+        } else {
+            output.push('$');
+            rest = &rest[1..];
+        }
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+fn resolve(expr: &str, lookup: Lookup) -> anyhow::Result<String> {
+    if let Some(path) = expr.strip_prefix(FILE_PREFIX) {
+        let content = fs::read_to_string(path.trim())
+            .with_context(|| format!("error reading secret file '{}'", path.trim()))?;
+        return Ok(content.trim_end_matches(['\r', '\n']).to_owned());
+    }
+    let (name, default) = match expr.split_once(DEFAULT_SEPARATOR) {
+        Some((name, default)) => (name.trim(), Some(default)),
+        None => (expr.trim(), None),
+    };
+    if name.is_empty() {
+        bail!("empty variable name in '${{{}}}'", expr);
+    }
+    match (lookup(name), default) {
+        (Ok(value), Some(default)) if value.is_empty() => Ok(default.to_owned()),
+        (Ok(value), _) => Ok(value),
+        (Err(_), Some(default)) => Ok(default.to_owned()),
+        (Err(env::VarError::NotPresent), None) => {
+            bail!("environment variable '{}' is not set", name)
+        }
+        (Err(e), None) => bail!("environment variable '{}': {}", name, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::env::VarError;
+
+    use super::{interpolate, interpolate_value_at};
+
+    /// Only `PARREQ_TEST_SECRET` is set, the process environment is left alone
+    fn lookup(name: &str) -> Result<String, VarError> {
+        HashMap::from([("PARREQ_TEST_SECRET", "s3cr3t")])
+            .get(name)
+            .map(|value| value.to_string())
+            .ok_or(VarError::NotPresent)
+    }
+
+    #[test]
+    fn it_should_resolve_env_vars_and_defaults() {
+        assert_eq!(
+            "pass=s3cr3t!",
+            interpolate("pass=${PARREQ_TEST_SECRET}!", &lookup).unwrap()
+        );
+        assert_eq!(
+            "fallback",
+            interpolate("${PARREQ_TEST_MISSING:-fallback}", &lookup).unwrap()
+        );
+        assert_eq!("$5 and ${x}", interpolate("$5 and $${x}", &lookup).unwrap());
+    }
+
+    #[test]
+    fn it_should_fail_on_missing_variables() {
+        let mut value: serde_yaml::Value =
+            serde_yaml::from_str("login:\n  password: ${PARREQ_TEST_MISSING}").unwrap();
+
+        let err = format!(
+            "{:#}",
+            interpolate_value_at(&mut value, &mut Vec::new(), &lookup).unwrap_err()
+        );
+
+        assert!(err.contains("login.password"));
+        assert!(err.contains("PARREQ_TEST_MISSING"));
+    }
+
+    #[test]
+    fn it_should_read_secret_files() {
+        let path = std::env::temp_dir().join(format!(
+            "parreq_interpolation_test_secret_{}",
+            std::process::id()
+        ));
+        std::fs::write(&path, "from-file\n").unwrap();
+
+        let resolved = interpolate(&format!("${{file:{}}}", path.display()), &lookup).unwrap();
+
+        assert_eq!("from-file", resolved);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,15 +1,29 @@
 use serde::{Deserialize, Serialize};
-use tracing::info;
+use tracing::{info, warn};
 
 use crate::config::LoginParameters;
+use crate::token_cache;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LoginResponse {
     pub access_token: String,
+    pub expires_in: Option<i64>,
+    /// Unix timestamp computed from `expires_in` when the token is received
+    #[serde(default)]
+    pub expires_at: Option<i64>,
 }
 
-pub async fn login(params: &LoginParameters) -> LoginResponse {
-    let client = reqwest::Client::new();
+pub async fn login(
+    client: &reqwest::Client,
+    params: &LoginParameters,
+    use_token_cache: bool,
+) -> LoginResponse {
+    if use_token_cache {
+        if let Some(login) = token_cache::load(params) {
+            info!("reusing cached token");
+            return login;
+        }
+    }
     let login_params = [
         ("username", &params.username),
         ("password", &params.password),
@@ -17,7 +31,7 @@ pub async fn login(params: &LoginParameters) -> LoginResponse {
         ("client_secret", &params.client_secret),
         ("grant_type", &params.grant_type),
     ];
-    let login: LoginResponse = client
+    let mut login: LoginResponse = client
         .post(&params.token_url)
         .form(&login_params)
         .send()
@@ -26,6 +40,14 @@ pub async fn login(params: &LoginParameters) -> LoginResponse {
         .json()
         .await
         .expect("error parsing access token");
+    login.expires_at = login
+        .expires_in
+        .map(|expires_in| chrono::Utc::now().timestamp() + expires_in);
     info!("logged in....");
+    if use_token_cache {
+        if let Err(e) = token_cache::store(params, &login) {
+            warn!("error caching token: {:?}", e);
+        }
+    }
     login
 }
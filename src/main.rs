@@ -1,30 +1,45 @@
 mod batch_executor;
 mod batcher;
+mod client;
 mod config;
+mod extract;
+mod feeder;
+mod interpolation;
 mod login;
 mod metrics;
+mod mix;
+mod pacing;
 mod request;
+mod requests_file;
+mod retry;
+mod scenario;
+mod signing;
+mod task;
+mod template;
+mod token_cache;
+mod trace_context;
 mod ui;
+mod warmup;
 
+use std::net::SocketAddr;
 use std::path::PathBuf;
-
-use config::RequestParameters;
-use confique::Config;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 use futures::future::join_all;
 use futures::stream::FuturesUnordered;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 
 use clap::Parser;
-use metrics::RequestMetric;
-use std::sync::mpsc::{channel, Sender};
 use tokio::task::JoinHandle;
-use tokio::time::Instant;
 use tracing::metadata::LevelFilter;
 use tracing::{info, instrument};
 use tracing_subscriber::fmt::format::FmtSpan;
-use tracing_subscriber::{fmt, prelude::*};
+use tracing_subscriber::{fmt, prelude::*, Layer, Registry};
 
 use crate::batch_executor::BatchExecutor;
+use crate::task::{Task, TaskContext, TaskParameters};
 
 /// Simple program to run several requests in parallel using authentication
 #[derive(Parser, Debug)]
@@ -36,6 +51,15 @@ struct Args {
     /// Config file with authentication and request entries [default: config.yaml]
     #[arg(short, long)]
     config: Option<PathBuf>,
+    /// Always request a new token instead of reusing a cached one
+    #[arg(long, default_value_t = false)]
+    no_token_cache: bool,
+    /// Seed of the random values, overrides the one in the config file
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Serve Prometheus metrics on http://<ADDRESS>/metrics during the run, e.g. 127.0.0.1:9464
+    #[arg(long, value_name = "ADDRESS")]
+    prometheus_listen: Option<SocketAddr>,
 }
 
 #[tokio::main]
@@ -43,91 +67,256 @@ async fn main() {
     let args = Args::parse();
 
     let config_file = args.config.unwrap_or(PathBuf::from("config.yaml"));
-    let conf =
-        config::Configuration::from_file(config_file).expect("error reading configuration file");
+    let conf = match config::Configuration::load(&config_file) {
+        Ok(conf) => conf,
+        Err(e) => {
+            eprintln!("error reading configuration file: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let requests_file = match conf
+        .requests_file
+        .as_ref()
+        .map(|path| requests_file::RequestsFile::open(path, &conf))
+        .transpose()
+    {
+        Ok(requests_file) => requests_file,
+        Err(e) => {
+            eprintln!("error reading requests file: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+    let file_requests = requests_file.as_ref().map_or(0, |file| file.len());
+
+    let feeders = match feeder::load_feeders(&conf.feeders, conf.concurrect_requests) {
+        Ok(feeders) => feeders,
+        Err(e) => {
+            eprintln!("error loading feeders: {:#}", e);
+            std::process::exit(1);
+        }
+    };
 
-    let total_requests = conf.iterations * conf.requests.len();
-    let requests_final = create_requests_from_configuration(&conf);
+    let conf = Arc::new(conf);
+    let seed = args.seed.or(conf.seed).unwrap_or_else(rand::random);
+    // identifies the run in the baggage sent to the servers and the metric sinks
+    let run_id = uuid::Builder::from_random_bytes(rand::random())
+        .into_uuid()
+        .to_string();
+    let tasks_final = create_tasks_from_configuration(&conf, requests_file.as_ref(), seed);
+    // weighted draws may run scenarios with more or fewer steps than others
+    let total_requests = tasks_final.expected_requests(file_requests);
 
-    let (metrics_sender, mut metrics_receiver) = channel::<RequestMetric>();
-    init_tracing(metrics_sender, args.verbose_output);
+    let (metrics_sender, mut metrics_receiver) = metrics::channel();
+    let mut metrics_senders = vec![metrics_sender];
+    if let Some(addr) = args.prometheus_listen {
+        // its counters and gauge can't miss a metric
+        let (prometheus_sender, prometheus_receiver) = metrics::lossless_channel();
+        if let Err(e) = metrics::serve_prometheus(addr, prometheus_receiver) {
+            eprintln!("error starting prometheus endpoint: {:#}", e);
+            std::process::exit(1);
+        }
+        metrics_senders.push(prometheus_sender);
+    }
+    let mut sink_writers = Vec::new();
+    for params in &conf.metric_sinks {
+        let (sink_sender, sink_receiver) = metrics::channel();
+        match metrics::SinkWriter::start(params, &run_id, sink_receiver) {
+            Ok(writer) => {
+                sink_writers.push(writer);
+                metrics_senders.push(sink_sender);
+            }
+            Err(e) => {
+                eprintln!("error starting metric sink: {:#}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    let mut extra_layers = Vec::new();
+    let otlp_exporter = match &conf.otlp {
+        Some(params) => {
+            let (otlp_sender, otlp_receiver) = metrics::channel();
+            match metrics::OtlpExporter::start(params, otlp_receiver) {
+                Ok((exporter, layer)) => {
+                    metrics_senders.push(otlp_sender);
+                    extra_layers.push(layer.boxed());
+                    Some(exporter)
+                }
+                Err(e) => {
+                    eprintln!("error starting otlp exporter: {:#}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => None,
+    };
+    let metrics_recorder = metrics::MetricsRecorder::new(metrics_senders);
+    let computed_metrics = metrics_recorder.computed();
+    init_tracing(extra_layers, args.verbose_output);
 
     info!("initialization");
 
-    let login_response = login::login(&conf.login).await;
-    let access_token = &login_response.access_token;
+    let clients = client::HttpClients::new(
+        &conf.tls,
+        conf.connect_timeout,
+        conf.request_timeout,
+        conf.all_requests()
+            .filter_map(|req| req.connect_timeout)
+            .chain(requests_file.iter().flat_map(|file| file.connect_timeouts())),
+    )
+    .expect("error creating http client");
+    let login_response =
+        login::login(clients.default_client(), &conf.login, !args.no_token_cache).await;
+    let context = Arc::new(TaskContext {
+        auth: Arc::from(login_response.access_token.as_str()),
+        clients,
+        feeders,
+        warmup: conf.warmup.map(|warmup| Arc::new(warmup::WarmupPhase::new(warmup))),
+        trace_context: conf
+            .trace_context
+            .as_ref()
+            .map(|params| Arc::new(trace_context::TraceContext::new(params, &run_id))),
+        metrics: metrics_recorder,
+        seed,
+    });
 
     let (start_signal_sender, start_signal_receiver) = tokio::sync::watch::channel(());
 
     let executors = create_executors(
         start_signal_receiver,
-        total_requests,
-        requests_final,
-        &access_token,
-        conf.concurrect_requests,
+        tasks_final,
+        &conf,
+        context,
     );
     info!("executors created");
     start_signal_sender
         .send(())
         .expect("error sending start signal");
-    if !args.verbose_output {
-        std::thread::spawn(move || {
-            ui::run_ui(total_requests, &mut metrics_receiver).expect("error running tui");
-        });
+    let pacing = conf.pacing;
+    // shown again once the tui is closed, to run the same requests again
+    println!("seed: {}", seed);
+    if conf.trace_context.is_some() || !conf.metric_sinks.is_empty() {
+        println!("run id: {}", run_id);
     }
-    join_all(executors).await;
+    // set once the executors are done, the tui then shows the last metrics and exits
+    let done = Arc::new(AtomicBool::new(false));
+    let ui_thread = (!args.verbose_output).then(|| {
+        let done = done.clone();
+        std::thread::spawn(move || {
+            ui::run_ui(
+                total_requests,
+                seed,
+                pacing,
+                &mut metrics_receiver,
+                computed_metrics,
+                done,
+            )
+            .expect("error running tui");
+        })
+    });
+    // a panicking executor leaves its remaining tasks unsent
+    let failed_executors = join_all(executors)
+        .await
+        .into_iter()
+        .filter(|result| result.is_err())
+        .count();
+    done.store(true, Ordering::Release);
 
     info!("Done!");
+    // the exporters and sinks send what is left, blocking until done
+    let shutdown = tokio::task::spawn_blocking(move || {
+        // the terminal is restored before anything else is printed
+        if let Some(ui_thread) = ui_thread {
+            if ui_thread.join().is_err() {
+                eprintln!("error running tui");
+            }
+        }
+        if let Some(exporter) = otlp_exporter {
+            if let Err(e) = exporter.shutdown() {
+                eprintln!("error exporting to otlp: {:#}", e);
+            }
+        }
+        for writer in sink_writers {
+            if let Err(e) = writer.shutdown() {
+                eprintln!("error writing metrics: {:#}", e);
+            }
+        }
+    });
+    shutdown.await.expect("error flushing metrics");
+    if failed_executors > 0 {
+        eprintln!(
+            "error running the requests: {} executor(s) stopped early",
+            failed_executors
+        );
+        std::process::exit(1);
+    }
 }
 
-fn create_requests_from_configuration(
-    conf: &config::Configuration,
-) -> impl Iterator<Item = RequestParameters> + Clone {
-    conf.requests
-        .clone()
-        .into_iter()
-        .cycle()
-        .take(conf.iterations)
+fn create_tasks_from_configuration(
+    conf: &Arc<config::Configuration>,
+    requests_file: Option<&requests_file::RequestsFile>,
+    seed: u64,
+) -> mix::TaskMix<impl Iterator<Item = TaskParameters> + Clone + Send + 'static> {
+    let requests = conf
+        .requests
+        .iter()
+        .map(|req| TaskParameters::Request(Box::new(conf.with_defaults(req.clone()))));
+    let scenarios = conf.scenarios.iter().map(|scenario| {
+        let mut scenario = scenario.clone();
+        scenario.steps = scenario
+            .steps
+            .into_iter()
+            .map(|step| conf.with_defaults(step))
+            .collect();
+        TaskParameters::Scenario(Arc::new(scenario))
+    });
+    let tasks: Vec<_> = requests.chain(scenarios).collect();
+    let file_tasks = requests_file.map(|file| {
+        let conf = conf.clone();
+        file.iter()
+            .map(move |req| match req {
+                Ok(req) => TaskParameters::Request(Box::new(conf.with_defaults(req))),
+                Err(e) => TaskParameters::Failed(Box::new(e)),
+            })
+    });
+    mix::TaskMix::new(tasks, conf.weights(), file_tasks, conf.iterations, seed)
 }
 
-#[instrument(skip(requests_final, access_token))]
+#[instrument(skip(tasks_final, conf, context))]
 fn create_executors(
     start_signal_receiver: tokio::sync::watch::Receiver<()>,
-    total_requests: usize,
-    requests_final: impl Iterator<Item = RequestParameters> + Clone + Send,
-    access_token: &str,
-    n_batches: usize,
+    tasks_final: impl Iterator<Item = TaskParameters> + Send + 'static,
+    conf: &config::Configuration,
+    context: Arc<TaskContext>,
 ) -> FuturesUnordered<JoinHandle<()>> {
     let executors = FuturesUnordered::new();
-    let requests_final = requests_final.clone();
-    let batches = batcher::split(requests_final.clone(), n_batches);
-    let batches = Box::new(batches);
-    let tasks_per_executor = total_requests / batches.len();
-
-    // creating a client is an expensive task
-    let client = reqwest::Client::new();
+    // each task is read (and parsed, for the requests file) once, by the
+    // first executor ready for it. Its number is its position in the run.
+    let batches = batcher::share(tasks_final.enumerate(), conf.concurrect_requests);
     let batch_executors: Vec<_> = batches
         .into_iter()
         .enumerate()
         .map(|(batch_counter, batch)| {
-            let start = Instant::now();
-            let tasks = batch
-                .enumerate()
-                .map(|(task_in_executor, req)| {
-                    let request = request::Request::new(
-                        req,
-                        access_token,
-                        batch_counter,
-                        tasks_per_executor,
-                        task_in_executor + 1,
-                        &client,
-                    );
-                    request
-                })
-                .collect();
-            info!("time creating tasks vector: {:?}", start.elapsed());
-            let batch_executor = BatchExecutor::new(batch_counter, tasks);
-            batch_executor
+            let pacing = pacing::Pacing::new(
+                conf.think_time.clone(),
+                conf.pacing,
+                StdRng::seed_from_u64(context.seed.wrapping_add(batch_counter as u64)),
+            );
+            let context = context.clone();
+            // tasks are created when the executor gets to them
+            let tasks =
+                batch
+                    .enumerate()
+                    .map(move |(task_in_executor, (task_index, params))| {
+                        Task::new(
+                            params,
+                            &context,
+                            batch_counter,
+                            task_in_executor + 1,
+                            task_index + 1,
+                        )
+                    });
+            BatchExecutor::new(batch_counter, tasks, pacing)
         })
         .collect();
 
@@ -141,10 +330,9 @@ fn create_executors(
     executors
 }
 
-fn init_tracing(metrics_sender: Sender<RequestMetric>, display_logs: bool) {
-    let mut layers = Vec::new();
-    let metrics_layer = metrics::MetricsLayer::new(metrics_sender).boxed();
-    layers.push(metrics_layer);
+/// Tracing only feeds the logs and the span exporters (`layers`), the
+/// metrics are reported by the requests themselves
+fn init_tracing(mut layers: Vec<Box<dyn Layer<Registry> + Send + Sync>>, display_logs: bool) {
     if display_logs {
         let log = fmt::layer()
             .with_span_events(FmtSpan::NEW | FmtSpan::CLOSE)
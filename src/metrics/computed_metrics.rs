@@ -1,72 +1,165 @@
-use std::{
-    sync::{
-        atomic::{AtomicU16, Ordering},
-    },
-};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
 
-use crate::metrics::request_metric::{RequestResult, RequestStatus};
+use super::request_metric::{CompletionResult, RequestMetric, RequestState};
 
-use super::{metrics_summary::MetricsSummary, request_metric::RequestMetric};
+/// Enough for the tokio workers, threads beyond share the shards
+const SHARDS: usize = 64;
 
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// Assigned round-robin on first use
+    static SHARD: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed) % SHARDS;
+}
+
+/// Counters of one thread, on its own cache line so the threads recording
+/// requests don't contend on it
+#[derive(Debug, Default)]
+#[repr(align(64))]
+struct Shard {
+    started: AtomicU64,
+    ok: AtomicU64,
+    errors: AtomicU64,
+    warmup: AtomicU64,
+    /// Counted in `ok` too
+    extraction_misses: AtomicU64,
+    skipped: AtomicU64,
+    /// Metrics not delivered to a consumer whose channel was full
+    dropped: AtomicU64,
+    /// Time spent recording the metrics
+    overhead_ns: AtomicU64,
+}
+
+/// Exact request counts, aggregated per thread as the requests report their
+/// metrics and summed when read. The consumers may miss some metrics when
+/// they can't keep up, these counts never do.
 #[derive(Debug)]
 pub(crate) struct ComputedMetrics {
-    in_progress: AtomicU16,
-    errors: AtomicU16,
-    ok: AtomicU16,
-    total_expected: u16,
-    // ok_durations: Arc<Mutex<Vec<Duration>>>,
-    // error_durations: Arc<Mutex<Vec<Duration>>>,
+    shards: Box<[Shard]>,
+}
+
+/// Sum of the shards at some point in time
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Counts {
+    pub in_progress: usize,
+    pub ok: usize,
+    pub errors: usize,
+    pub warmup: usize,
+    /// Succeeded, but an `extract` expression found no value
+    pub extraction_misses: usize,
+    /// Scenario steps not sent because a previous step failed
+    pub skipped: usize,
+    pub dropped: usize,
+    /// Average time spent recording the metrics of a completed request
+    pub overhead_per_request: Duration,
 }
 
 impl ComputedMetrics {
-    pub(super) fn new(total_expected: u16) -> Self {
+    pub(super) fn new() -> Self {
         Self {
-            in_progress: AtomicU16::new(0),
-            errors: AtomicU16::new(0),
-            ok: AtomicU16::new(0),
-            total_expected,
-            // ok_durations: Arc::new(Mutex::new(Vec::with_capacity(total_expected as usize))),
-            // error_durations: Arc::new(Mutex::new(Vec::with_capacity(total_expected as usize))),
+            shards: (0..SHARDS).map(|_| Shard::default()).collect(),
         }
     }
+
+    fn shard(&self) -> &Shard {
+        &self.shards[SHARD.with(|shard| *shard)]
+    }
+
     pub(super) fn record(&self, metric: &RequestMetric) {
-        match &metric.status {
-            RequestStatus::InProgress => {
-                self.in_progress.fetch_add(1, Ordering::AcqRel);
+        let shard = self.shard();
+        match metric.status() {
+            RequestState::InProgress(_) => shard.started.fetch_add(1, Ordering::Relaxed),
+            RequestState::Completed(_) if metric.is_warmup() => {
+                shard.warmup.fetch_add(1, Ordering::Relaxed)
             }
-            RequestStatus::Completed(cs) => {
-                let _duration = metric.request_duration();
-                self.in_progress.fetch_sub(1, Ordering::AcqRel);
-                match cs {
-                    RequestResult::Error(_) => {
-                        // let mut error_durations = self
-                        //     .error_durations
-                        //     .lock()
-                        //     .expect("failed to get the ok_times lock");
-                        // error_durations.push(duration);
-                        self.errors.fetch_add(1, Ordering::AcqRel);
-                    },
-                    RequestResult::Ok => {
-                        // let mut ok_times = self
-                        //     .ok_durations
-                        //     .lock()
-                        //     .expect("failed to get the ok_times lock");
-                        // ok_times.push(duration);
-                        self.ok.fetch_add(1, Ordering::Acquire);
+            RequestState::Completed(completed) => match completed.result {
+                CompletionResult::Ok => {
+                    if metric.extraction_missed() {
+                        shard.extraction_misses.fetch_add(1, Ordering::Relaxed);
                     }
-                };
-            }
-        }
+                    shard.ok.fetch_add(1, Ordering::Relaxed)
+                }
+                CompletionResult::Error => shard.errors.fetch_add(1, Ordering::Relaxed),
+                CompletionResult::Skipped => shard.skipped.fetch_add(1, Ordering::Relaxed),
+            },
+        };
     }
 
-    pub(crate) fn summary(&self) -> MetricsSummary {
-        MetricsSummary {
-            in_progress: self.in_progress.load(Ordering::Relaxed).into(),
-            ok: self.ok.load(Ordering::Relaxed).into(),
-            errors: self.errors.load(Ordering::Relaxed).into(),
-            total_expected: self.total_expected.into(),
-            // ok_durations: self.ok_durations.lock().expect("error getting...").clone(),
-            // error_durations: self.error_durations.lock().expect("error getting...").clone(),
+    pub(super) fn record_dropped(&self) {
+        self.shard().dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn record_overhead(&self, overhead: Duration) {
+        self.shard()
+            .overhead_ns
+            .fetch_add(overhead.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn counts(&self) -> Counts {
+        let sum = |counter: fn(&Shard) -> &AtomicU64| {
+            self.shards
+                .iter()
+                .map(|shard| counter(shard).load(Ordering::Relaxed))
+                .sum::<u64>()
+        };
+        let (started, ok, errors, warmup, skipped) = (
+            sum(|shard| &shard.started),
+            sum(|shard| &shard.ok),
+            sum(|shard| &shard.errors),
+            sum(|shard| &shard.warmup),
+            sum(|shard| &shard.skipped),
+        );
+        let completed = ok + errors + warmup + skipped;
+        Counts {
+            // completions may be summed before their start
+            in_progress: started.saturating_sub(completed) as usize,
+            ok: ok as usize,
+            errors: errors as usize,
+            warmup: warmup as usize,
+            extraction_misses: sum(|shard| &shard.extraction_misses) as usize,
+            skipped: skipped as usize,
+            dropped: sum(|shard| &shard.dropped) as usize,
+            overhead_per_request: Duration::from_nanos(
+                sum(|shard| &shard.overhead_ns) / completed.max(1),
+            ),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::ComputedMetrics;
+    use crate::metrics::request_metric::{CompletionResult, RequestMetric};
+
+    #[test]
+    fn it_should_sum_the_counts_of_every_thread() {
+        let computed = Arc::new(ComputedMetrics::new());
+        let threads: Vec<_> = (0..4)
+            .map(|thread| {
+                let computed = computed.clone();
+                std::thread::spawn(move || {
+                    for i in 0..100 {
+                        let mut metric = RequestMetric::new("", thread, false);
+                        computed.record(&metric);
+                        if i % 10 != 0 {
+                            metric.mark_end(CompletionResult::Ok);
+                            computed.record(&metric);
+                        }
+                    }
+                })
+            })
+            .collect();
+        threads
+            .into_iter()
+            .for_each(|thread| thread.join().unwrap());
+
+        let counts = computed.counts();
+
+        assert_eq!(360, counts.ok);
+        assert_eq!(40, counts.in_progress);
+        assert_eq!(0, counts.errors);
+    }
+}
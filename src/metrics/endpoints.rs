@@ -0,0 +1,182 @@
+use std::cmp::Ordering;
+use std::time::Duration;
+
+use super::metrics_summary::NamedMetrics;
+
+/// Columns of the endpoints table, in display order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EndpointColumn {
+    Name,
+    Count,
+    Share,
+    Rps,
+    ErrorRate,
+    P50,
+    P95,
+    P99,
+    LastStatus,
+}
+
+impl EndpointColumn {
+    pub const ALL: [EndpointColumn; 9] = [
+        EndpointColumn::Name,
+        EndpointColumn::Count,
+        EndpointColumn::Share,
+        EndpointColumn::Rps,
+        EndpointColumn::ErrorRate,
+        EndpointColumn::P50,
+        EndpointColumn::P95,
+        EndpointColumn::P99,
+        EndpointColumn::LastStatus,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            EndpointColumn::Name => "Request",
+            EndpointColumn::Count => "Count",
+            EndpointColumn::Share => "Share",
+            EndpointColumn::Rps => "Req/s",
+            EndpointColumn::ErrorRate => "Error %",
+            EndpointColumn::P50 => "p50 ms",
+            EndpointColumn::P95 => "p95 ms",
+            EndpointColumn::P99 => "p99 ms",
+            EndpointColumn::LastStatus => "Status",
+        }
+    }
+
+    /// The next column to the right, wrapping around
+    pub fn next(&self) -> Self {
+        let index = Self::ALL
+            .iter()
+            .position(|column| column == self)
+            .unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    pub fn previous(&self) -> Self {
+        let index = Self::ALL
+            .iter()
+            .position(|column| column == self)
+            .unwrap_or(0);
+        Self::ALL[(index + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
+}
+
+/// Metrics of the requests sharing a name, as shown in the endpoints table
+#[derive(Debug)]
+pub(crate) struct EndpointRow<'a> {
+    pub name: &'a str,
+    pub count: usize,
+    /// Percentage of all the completed requests, the achieved request mix
+    pub share: f64,
+    pub rps: f64,
+    pub error_rate: f64,
+    pub p50_us: u64,
+    pub p95_us: u64,
+    pub p99_us: u64,
+    pub last_status: Option<u16>,
+}
+
+impl<'a> EndpointRow<'a> {
+    /// `completed` counts the requests of all the names, `elapsed` is the
+    /// time since the start of the run
+    pub fn new(name: &'a str, named: &NamedMetrics, completed: usize, elapsed: Duration) -> Self {
+        let count = named.ok + named.errors;
+        Self {
+            name,
+            count,
+            share: count as f64 * 100f64 / completed.max(1) as f64,
+            rps: count as f64 / elapsed.as_secs_f64().max(1f64),
+            error_rate: named.errors as f64 * 100f64 / count.max(1) as f64,
+            p50_us: named.latencies_us.value_at_quantile(0.5),
+            p95_us: named.latencies_us.value_at_quantile(0.95),
+            p99_us: named.latencies_us.value_at_quantile(0.99),
+            last_status: named.last_status,
+        }
+    }
+
+    fn compare(&self, other: &Self, column: EndpointColumn) -> Ordering {
+        match column {
+            EndpointColumn::Name => self.name.cmp(other.name),
+            EndpointColumn::Count => self.count.cmp(&other.count),
+            EndpointColumn::Share => self.share.total_cmp(&other.share),
+            EndpointColumn::Rps => self.rps.total_cmp(&other.rps),
+            EndpointColumn::ErrorRate => self.error_rate.total_cmp(&other.error_rate),
+            EndpointColumn::P50 => self.p50_us.cmp(&other.p50_us),
+            EndpointColumn::P95 => self.p95_us.cmp(&other.p95_us),
+            EndpointColumn::P99 => self.p99_us.cmp(&other.p99_us),
+            EndpointColumn::LastStatus => self.last_status.cmp(&other.last_status),
+        }
+    }
+}
+
+/// Ties keep the name order
+pub(super) fn sort_endpoints(
+    rows: &mut [EndpointRow<'_>],
+    column: EndpointColumn,
+    descending: bool,
+) {
+    rows.sort_by(|a, b| {
+        let ordering = a.compare(b, column);
+        if descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{sort_endpoints, EndpointColumn, EndpointRow};
+    use crate::metrics::metrics_summary::NamedMetrics;
+
+    fn named(ok: usize, errors: usize, latency_us: u64) -> NamedMetrics {
+        let mut named = NamedMetrics {
+            ok,
+            errors,
+            ..Default::default()
+        };
+        for _ in 0..ok + errors {
+            named.latencies_us.record(latency_us).unwrap();
+        }
+        named
+    }
+
+    #[test]
+    fn it_should_sort_the_endpoints_by_column() {
+        let (fast, slow, failing) = (
+            named(30, 0, 2_000),
+            named(10, 0, 90_000),
+            named(5, 5, 1_000),
+        );
+        let elapsed = Duration::from_secs(10);
+        let mut rows = vec![
+            EndpointRow::new("fast", &fast, 50, elapsed),
+            EndpointRow::new("failing", &failing, 50, elapsed),
+            EndpointRow::new("slow", &slow, 50, elapsed),
+        ];
+
+        sort_endpoints(&mut rows, EndpointColumn::P99, true);
+        assert_eq!(
+            vec!["slow", "fast", "failing"],
+            rows.iter().map(|row| row.name).collect::<Vec<_>>()
+        );
+        sort_endpoints(&mut rows, EndpointColumn::ErrorRate, true);
+        assert_eq!("failing", rows[0].name);
+        assert_eq!(50f64, rows[0].error_rate);
+        sort_endpoints(&mut rows, EndpointColumn::Rps, false);
+        assert_eq!(
+            vec![1f64, 1f64, 3f64],
+            rows.iter().map(|row| row.rps).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![20f64, 20f64, 60f64],
+            rows.iter().map(|row| row.share).collect::<Vec<_>>()
+        );
+        assert_eq!(EndpointColumn::LastStatus, EndpointColumn::Name.previous());
+        assert_eq!(EndpointColumn::Count, EndpointColumn::Name.next());
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Local};
+
+use super::request_metric::RequestMetric;
+
+/// Distinct errors kept, the least recent ones are forgotten beyond
+const MAX_ERRORS: usize = 1_000;
+
+/// What makes two failures the same error: the url is left out, as a
+/// templated one differs on every request
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ErrorKey {
+    name: String,
+    status_code: Option<u16>,
+    message: String,
+}
+
+/// Failures of the same request with the same status and message
+#[derive(Debug, Clone)]
+pub(crate) struct ErrorEntry {
+    pub name: String,
+    /// Of the last failure
+    pub url: String,
+    pub status_code: Option<u16>,
+    pub message: String,
+    pub count: usize,
+    pub last_seen: DateTime<Local>,
+    /// Of the last failure, when sent with a `traceparent` header
+    pub trace_id: Option<String>,
+    /// Position of the last failure in `ErrorLog::recency`
+    recorded: u64,
+}
+
+/// Recent failures, identical ones grouped together
+#[derive(Debug, Default)]
+pub(crate) struct ErrorLog {
+    errors: HashMap<ErrorKey, ErrorEntry>,
+    /// Keys in the order of their last failure, the first one is forgotten
+    /// beyond `MAX_ERRORS`
+    recency: BTreeMap<u64, ErrorKey>,
+    recorded: u64,
+}
+
+impl ErrorLog {
+    pub fn record(&mut self, metric: &RequestMetric, at: DateTime<Local>) {
+        let key = ErrorKey {
+            name: metric.name().to_owned(),
+            status_code: metric.status_code(),
+            message: metric.error_message().unwrap_or_default().to_owned(),
+        };
+        if !self.errors.contains_key(&key) && self.errors.len() >= MAX_ERRORS {
+            if let Some((_, least_recent)) = self.recency.pop_first() {
+                self.errors.remove(&least_recent);
+            }
+        }
+        self.recorded += 1;
+        let entry = self
+            .errors
+            .entry(key.clone())
+            .or_insert_with_key(|key| ErrorEntry {
+                name: key.name.clone(),
+                url: String::new(),
+                status_code: key.status_code,
+                message: key.message.clone(),
+                count: 0,
+                last_seen: at,
+                trace_id: None,
+                recorded: 0,
+            });
+        entry.count += 1;
+        if at >= entry.last_seen {
+            entry.last_seen = at;
+            entry.url = metric.url().unwrap_or_default().to_owned();
+            entry.trace_id = metric.trace_id().map(str::to_owned);
+        }
+        self.recency.remove(&entry.recorded);
+        entry.recorded = self.recorded;
+        self.recency.insert(self.recorded, key);
+    }
+
+    /// Most recent first
+    pub fn entries(&self) -> Vec<&ErrorEntry> {
+        let mut entries: Vec<_> = self.errors.values().collect();
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.last_seen));
+        entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Local};
+
+    use super::{ErrorLog, MAX_ERRORS};
+    use crate::metrics::RequestMetric;
+    use crate::request::{ErrorClass, RequestError};
+
+    fn failed(name: &str, message: &str) -> RequestMetric {
+        failed_at(name, &format!("http://localhost/{}", name), message)
+    }
+
+    fn failed_at(name: &str, url: &str, message: &str) -> RequestMetric {
+        let mut metric = RequestMetric::new(name, 0, false);
+        metric.set_url(url.to_owned());
+        metric.set_trace_id(format!("{}-{}", url, message));
+        metric.complete(&Err(RequestError::new(
+            ErrorClass::Request,
+            message.to_owned(),
+        )));
+        metric
+    }
+
+    #[test]
+    fn it_should_group_identical_errors_most_recent_first() {
+        let now = Local::now();
+        let mut log = ErrorLog::default();
+        log.record(&failed("orders", "connection reset"), now);
+        log.record(
+            &failed("users", "connection reset"),
+            now + Duration::seconds(1),
+        );
+        log.record(
+            &failed("orders", "connection reset"),
+            now + Duration::seconds(2),
+        );
+
+        let entries = log.entries();
+
+        assert_eq!(2, log.len());
+        assert_eq!("orders", entries[0].name);
+        assert_eq!("http://localhost/orders", entries[0].url);
+        assert_eq!(2, entries[0].count);
+        assert_eq!(
+            Some("http://localhost/orders-connection reset"),
+            entries[0].trace_id.as_deref()
+        );
+        assert_eq!("users", entries[1].name);
+        assert_eq!(1, entries[1].count);
+    }
+
+    #[test]
+    fn it_should_group_templated_urls_and_forget_the_least_recent() {
+        let now = Local::now();
+        let mut log = ErrorLog::default();
+        for i in 0..3 {
+            let url = format!("http://localhost/orders/{}", i);
+            log.record(
+                &failed_at("orders", &url, "not found"),
+                now + Duration::seconds(i),
+            );
+        }
+        assert_eq!(1, log.len());
+        assert_eq!("http://localhost/orders/2", log.entries()[0].url);
+        assert_eq!(3, log.entries()[0].count);
+
+        for i in 0..MAX_ERRORS as i64 {
+            log.record(
+                &failed("users", &i.to_string()),
+                now + Duration::seconds(3 + i),
+            );
+        }
+        assert_eq!(MAX_ERRORS, log.len());
+        assert!(log.entries().iter().all(|entry| entry.name == "users"));
+        assert_eq!(MAX_ERRORS, log.recency.len());
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use hdrhistogram::Histogram;
+use tokio::time::Instant;
+
+use super::metrics_summary::latency_histogram;
+
+/// Latencies of the requests completed during the last seconds, kept in one
+/// histogram per second so the old ones are dropped as a whole
+#[derive(Debug)]
+pub(crate) struct LatencyWindow {
+    start: Instant,
+    window: Duration,
+    /// (second of the run, latencies in microseconds), oldest first
+    seconds: VecDeque<(u64, Histogram<u64>)>,
+}
+
+impl LatencyWindow {
+    pub fn new(start: Instant, window: Duration) -> Self {
+        Self {
+            start,
+            window,
+            seconds: VecDeque::new(),
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    fn second(&self, instant: Instant) -> u64 {
+        instant.saturating_duration_since(self.start).as_secs()
+    }
+
+    pub fn record(&mut self, end: Instant, latency_us: u64) {
+        let second = self.second(end);
+        // the metrics of concurrent requests don't arrive in order
+        let position = self.seconds.iter().rposition(|(s, _)| *s <= second);
+        let histogram = match position {
+            Some(position) if self.seconds[position].0 == second => &mut self.seconds[position].1,
+            _ => {
+                let index = position.map_or(0, |position| position + 1);
+                self.seconds.insert(index, (second, latency_histogram()));
+                &mut self.seconds[index].1
+            }
+        };
+        histogram.saturating_record(latency_us);
+        while let Some((oldest, _)) = self.seconds.front() {
+            if oldest + self.window.as_secs() > second {
+                break;
+            }
+            self.seconds.pop_front();
+        }
+    }
+
+    /// Latencies of the requests completed within the window before `now`
+    pub fn latencies_us(&self, now: Instant) -> Histogram<u64> {
+        let now = self.second(now);
+        let mut latencies = latency_histogram();
+        for (_, histogram) in self
+            .seconds
+            .iter()
+            .filter(|(second, _)| second + self.window.as_secs() > now)
+        {
+            latencies.add(histogram).expect("same histogram bounds");
+        }
+        latencies
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::LatencyWindow;
+
+    #[test]
+    fn it_should_only_keep_the_latencies_of_the_window() {
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_millis(secs * 1000 + 500);
+        let mut window = LatencyWindow::new(start, Duration::from_secs(10));
+        window.record(at(0), 1_000_000);
+        window.record(at(5), 5_000);
+        window.record(at(3), 3_000);
+        window.record(at(10), 10_000);
+
+        let latencies = window.latencies_us(at(10));
+
+        assert_eq!(3, latencies.len());
+        assert!(latencies.equivalent(10_000, latencies.max()));
+        assert!(latencies.equivalent(3_000, latencies.min()));
+        assert_eq!(0, window.latencies_us(at(30)).len());
+    }
+}
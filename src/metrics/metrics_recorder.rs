@@ -0,0 +1,105 @@
+use std::sync::mpsc::{Receiver, Sender, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::time::Instant;
+
+use super::computed_metrics::ComputedMetrics;
+use super::request_metric::RequestMetric;
+
+/// Metrics buffered for a consumer before the new ones are dropped
+const CHANNEL_CAPACITY: usize = 100_000;
+
+/// Bounded channel from the recorder to one of its consumers
+pub(crate) fn channel() -> (MetricsSender, Receiver<RequestMetric>) {
+    let (sender, receiver) = std::sync::mpsc::sync_channel(CHANNEL_CAPACITY);
+    (MetricsSender::Bounded(sender), receiver)
+}
+
+/// Unbounded channel, for a consumer keeping running counts (the in-flight
+/// gauge, the request counters) that would drift with a dropped metric. It
+/// must keep up with the requests, its backlog isn't limited.
+pub(crate) fn lossless_channel() -> (MetricsSender, Receiver<RequestMetric>) {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    (MetricsSender::Unbounded(sender), receiver)
+}
+
+pub(crate) enum MetricsSender {
+    /// Drops the metrics once full
+    Bounded(SyncSender<RequestMetric>),
+    Unbounded(Sender<RequestMetric>),
+}
+
+/// Where the requests report their metrics, when sent and when completed.
+/// The counts are aggregated on the spot, every consumer (the tui and the
+/// exporters) gets its own copy of the metric. Recording never blocks: a
+/// metric is dropped for a consumer that can't keep up.
+#[derive(Clone)]
+pub(crate) struct MetricsRecorder {
+    computed: Arc<ComputedMetrics>,
+    senders: Arc<[MetricsSender]>,
+}
+
+impl MetricsRecorder {
+    pub(crate) fn new(senders: Vec<MetricsSender>) -> Self {
+        Self {
+            computed: Arc::new(ComputedMetrics::new()),
+            senders: senders.into(),
+        }
+    }
+
+    pub(crate) fn computed(&self) -> Arc<ComputedMetrics> {
+        self.computed.clone()
+    }
+
+    pub(crate) fn record(&self, metric: &RequestMetric) {
+        let start = Instant::now();
+        self.computed.record(metric);
+        for sender in self.senders.iter() {
+            match sender {
+                MetricsSender::Bounded(sender) => match sender.try_send(metric.clone()) {
+                    Err(TrySendError::Full(_)) => self.computed.record_dropped(),
+                    // a consumer that went away (tui closed) doesn't stop the others
+                    Ok(()) | Err(TrySendError::Disconnected(_)) => {}
+                },
+                MetricsSender::Unbounded(sender) => {
+                    let _ = sender.send(metric.clone());
+                }
+            }
+        }
+        self.computed.record_overhead(start.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::sync_channel;
+
+    use super::{MetricsRecorder, MetricsSender};
+    use crate::metrics::RequestMetric;
+
+    #[test]
+    fn it_should_drop_metrics_when_a_consumer_is_full() {
+        let (sender, receiver) = sync_channel(2);
+        let recorder = MetricsRecorder::new(vec![MetricsSender::Bounded(sender)]);
+        for _ in 0..5 {
+            recorder.record(&RequestMetric::new("", 0, false));
+        }
+
+        let counts = recorder.computed().counts();
+
+        assert_eq!(2, receiver.try_iter().count());
+        assert_eq!(3, counts.dropped);
+        assert_eq!(5, counts.in_progress);
+    }
+
+    #[test]
+    fn it_should_not_drop_metrics_for_a_lossless_consumer() {
+        let (sender, receiver) = super::lossless_channel();
+        let recorder = MetricsRecorder::new(vec![sender]);
+        for _ in 0..5 {
+            recorder.record(&RequestMetric::new("", 0, false));
+        }
+
+        assert_eq!(5, receiver.try_iter().count());
+        assert_eq!(0, recorder.computed().counts().dropped);
+    }
+}
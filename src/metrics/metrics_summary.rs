@@ -1,26 +1,119 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
 
+use chrono::{DateTime, Local};
+use hdrhistogram::Histogram;
+use tokio::time::Instant;
 
+use super::computed_metrics::Counts;
+use super::endpoints::{sort_endpoints, EndpointColumn, EndpointRow};
+use super::error_log::ErrorLog;
+use super::latency_window::LatencyWindow;
+use super::name_limit::{MAX_NAMES, OTHER_NAMES};
 use super::request_metric::{CompletionResult, RequestMetric, RequestState};
+use super::throughput::Throughput;
+
+/// Span of the rolling latency percentiles
+const LATENCY_WINDOW: Duration = Duration::from_secs(10);
+/// The corrected latencies are rebuilt from all the latencies, at most this often
+const CORRECTION_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Counts for the requests sharing a name (each scenario step has its own)
+#[derive(Debug)]
+pub(crate) struct NamedMetrics {
+    pub ok: usize,
+    pub errors: usize,
+    pub last_status: Option<u16>,
+    /// Less precise than the run latencies, there is one per name
+    pub latencies_us: Histogram<u64>,
+}
+
+impl Default for NamedMetrics {
+    fn default() -> Self {
+        Self {
+            ok: 0,
+            errors: 0,
+            last_status: None,
+            latencies_us: Histogram::new_with_max(3_600_000_000, 2)
+                .expect("valid histogram bounds"),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub(crate) struct MetricsSummary {
     pub in_progress: usize,
     pub errors: usize,
     pub ok: usize,
+    pub timeouts: usize,
+    /// Completed during the warm-up, not counted anywhere else
+    pub warmup: usize,
+    /// Counted in `ok`, an `extract` expression found no value
+    pub extraction_misses: usize,
+    /// Scenario steps not sent because a previous step failed, neither ok
+    /// nor errors
+    pub skipped: usize,
+    pub first_attempt_errors: usize,
+    pub retries: usize,
+    pub errors_by_class: HashMap<String, usize>,
+    pub error_log: ErrorLog,
+    pub by_name: BTreeMap<String, NamedMetrics>,
     pub total_expected: usize,
     pub ok_durations_ms: Vec<f32>,
     pub error_durations_ms: Vec<f32>,
+    pub throughput: Throughput,
+    /// Durations of all the completed requests, in microseconds
+    pub latencies_us: Histogram<u64>,
+    /// Durations of the requests completed during the last seconds
+    pub latency_window: LatencyWindow,
+    /// `latencies_us` corrected for coordinated omission when last rebuilt
+    corrected_latencies_us: Histogram<u64>,
+    /// New latencies were recorded since the last rebuild
+    corrected_stale: bool,
+    corrected_at: Option<Instant>,
+    /// Intervals between the starts of two requests of the same executor
+    start_intervals_us: Histogram<u64>,
+    last_start_by_executor: HashMap<u64, Instant>,
+    /// Interval set by the pacing
+    pacing: Option<Duration>,
+    /// Wall clock time at `start`, to date the errors
+    start: (Instant, DateTime<Local>),
+    /// Metrics the tui or an exporter couldn't keep up with
+    pub dropped: usize,
+    pub overhead_per_request: Duration,
 }
 
 impl MetricsSummary {
-    pub fn new(total_expected: usize) -> Self {
+    pub fn new(total_expected: usize, pacing: Option<Duration>) -> Self {
+        let start = Instant::now();
         Self {
             in_progress: 0,
             errors: 0,
             ok: 0,
+            timeouts: 0,
+            warmup: 0,
+            extraction_misses: 0,
+            skipped: 0,
+            first_attempt_errors: 0,
+            retries: 0,
+            errors_by_class: HashMap::new(),
+            error_log: ErrorLog::default(),
+            by_name: BTreeMap::new(),
             total_expected,
             ok_durations_ms: Vec::with_capacity(total_expected),
             error_durations_ms: Vec::with_capacity(total_expected),
+            throughput: Throughput::new(start),
+            latencies_us: latency_histogram(),
+            latency_window: LatencyWindow::new(start, LATENCY_WINDOW),
+            corrected_latencies_us: latency_histogram(),
+            corrected_stale: false,
+            corrected_at: None,
+            start_intervals_us: latency_histogram(),
+            last_start_by_executor: HashMap::new(),
+            pacing,
+            start: (start, Local::now()),
+            dropped: 0,
+            overhead_per_request: Duration::ZERO,
         }
     }
 
@@ -30,12 +123,64 @@ impl MetricsSummary {
                 self.in_progress += 1;
             }
             RequestState::Completed(completion_result) => {
-                self.in_progress -= 1;
+                // its start may have been dropped
+                self.in_progress = self.in_progress.saturating_sub(1);
+                if metrics.is_warmup() {
+                    self.warmup += 1;
+                    return;
+                }
+                if let CompletionResult::Skipped = completion_result.result {
+                    self.skipped += 1;
+                    return;
+                }
                 let duration = completion_result.end - completion_result.start;
+                self.throughput.record(
+                    completion_result.end,
+                    matches!(completion_result.result, CompletionResult::Error),
+                );
+                self.record_latency(metrics.executor_id(), completion_result.start, duration);
+                self.latency_window
+                    .record(completion_result.end, duration.as_micros() as u64);
+                self.retries += metrics.attempts().saturating_sub(1) as usize;
+                if metrics.first_attempt_failed() {
+                    self.first_attempt_errors += 1;
+                }
+                // the same names as the other exporters, see `NameLimit`
+                let name = if self.by_name.len() < MAX_NAMES
+                    || self.by_name.contains_key(metrics.name())
+                {
+                    metrics.name()
+                } else {
+                    OTHER_NAMES
+                };
+                let named = self.by_name.entry(name.to_owned()).or_default();
+                named
+                    .latencies_us
+                    .saturating_record(duration.as_micros() as u64);
+                if let Some(status_code) = metrics.status_code() {
+                    named.last_status = Some(status_code);
+                }
+                match completion_result.result {
+                    CompletionResult::Error => named.errors += 1,
+                    CompletionResult::Ok => named.ok += 1,
+                    CompletionResult::Skipped => {}
+                }
                 match completion_result.result {
                     CompletionResult::Error => {
                         self.errors += 1;
-                        self.error_durations_ms.push(duration.as_secs_f32() * 1000_f32);
+                        let class = metrics.error_class().unwrap_or("request");
+                        if class == "timeout" {
+                            self.timeouts += 1;
+                        }
+                        *self.errors_by_class.entry(class.to_owned()).or_default() += 1;
+                        let (start, start_time) = self.start;
+                        let elapsed = completion_result.end.saturating_duration_since(start);
+                        self.error_log.record(
+                            &metrics,
+                            start_time + chrono::Duration::from_std(elapsed).unwrap_or_default(),
+                        );
+                        self.error_durations_ms
+                            .push(duration.as_secs_f32() * 1000_f32);
                         self.ok_durations_ms.push(0f32);
                     }
                     CompletionResult::Ok => {
@@ -43,12 +188,216 @@ impl MetricsSummary {
                         self.ok_durations_ms.push(duration.as_secs_f32() * 1000_f32);
                         self.error_durations_ms.push(0f32);
                     }
+                    CompletionResult::Skipped => {}
                 }
             }
         }
     }
 
+    fn record_latency(&mut self, executor_id: u64, start: Instant, duration: Duration) {
+        self.latencies_us
+            .saturating_record(duration.as_micros() as u64);
+        self.corrected_stale = true;
+        // executors send their requests one after the other
+        if let Some(last_start) = self.last_start_by_executor.insert(executor_id, start) {
+            let interval = start.saturating_duration_since(last_start);
+            self.start_intervals_us
+                .saturating_record(interval.as_micros() as u64);
+        }
+    }
+
+    /// Expected interval between the starts of two requests of an executor,
+    /// in microseconds: the pacing when set, the observed median otherwise
+    pub fn expected_interval_us(&self) -> u64 {
+        match self.pacing {
+            Some(pacing) => pacing.as_micros() as u64,
+            None => self.start_intervals_us.value_at_quantile(0.5),
+        }
+    }
+
+    /// Latencies corrected for coordinated omission. While a request stalls,
+    /// its executor doesn't send the ones it was expected to send: they are
+    /// added with the latency they would have had, as HdrHistogram does.
+    /// Only as recent as the last `update_corrected_latencies`.
+    pub fn corrected_latencies_us(&self) -> &Histogram<u64> {
+        &self.corrected_latencies_us
+    }
+
+    /// Rebuilds the corrected latencies when new ones were recorded, at most
+    /// every `CORRECTION_INTERVAL` unless `force`d (for the last frame)
+    pub fn update_corrected_latencies(&mut self, now: Instant, force: bool) {
+        let due = self.corrected_at.is_none_or(|corrected_at| {
+            now.saturating_duration_since(corrected_at) >= CORRECTION_INTERVAL
+        });
+        if self.corrected_stale && (due || force) {
+            self.corrected_latencies_us =
+                self.latencies_us.clone_correct(self.expected_interval_us());
+            self.corrected_stale = false;
+            self.corrected_at = Some(now);
+        }
+    }
+
+    /// Replaces the counts with the exact ones, the received metrics may miss
+    /// some of the requests
+    pub fn set_counts(&mut self, counts: Counts) {
+        self.in_progress = counts.in_progress;
+        self.ok = counts.ok;
+        self.errors = counts.errors;
+        self.warmup = counts.warmup;
+        self.extraction_misses = counts.extraction_misses;
+        self.skipped = counts.skipped;
+        self.dropped = counts.dropped;
+        self.overhead_per_request = counts.overhead_per_request;
+    }
+
+    /// One row per request name, sorted by `column`
+    pub fn endpoints(&self, column: EndpointColumn, descending: bool) -> Vec<EndpointRow<'_>> {
+        let elapsed = Instant::now().saturating_duration_since(self.start.0);
+        let mut rows: Vec<_> = self
+            .by_name
+            .iter()
+            .map(|(name, named)| EndpointRow::new(name, named, self.ok + self.errors, elapsed))
+            .collect();
+        sort_endpoints(&mut rows, column, descending);
+        rows
+    }
+
+    /// Number of requests per latency range, as (upper bound in microseconds,
+    /// count), the ranges splitting evenly the latencies up to the p99
+    pub fn latency_distribution(&self, ranges: usize) -> Vec<(u64, u64)> {
+        let low = self.latencies_us.min();
+        let high = self.latencies_us.value_at_quantile(0.99);
+        let width = ((high - low) / ranges.max(1) as u64).max(1);
+        let mut distribution: Vec<_> = (1..=ranges as u64)
+            .map(|range| (low + width * range, 0))
+            .collect();
+        for value in self.latencies_us.iter_recorded() {
+            let latency = value.value_iterated_to();
+            if latency > high {
+                break;
+            }
+            let range = (((latency - low) / width) as usize).min(ranges.saturating_sub(1));
+            if let Some((_, count)) = distribution.get_mut(range) {
+                *count += value.count_at_value();
+            }
+        }
+        distribution
+    }
+
+    /// Requests done, sent or skipped
+    pub fn completed(&self) -> usize {
+        self.warmup + self.ok + self.errors + self.skipped
+    }
+
     pub fn is_completed(&self) -> bool {
-        self.total_expected == self.ok + self.errors
+        self.total_expected == self.completed()
+    }
+}
+
+/// Up to an hour in microseconds, longer values are recorded as an hour
+pub(super) fn latency_histogram() -> Histogram<u64> {
+    Histogram::new_with_max(3_600_000_000, 3).expect("valid histogram bounds")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::{MetricsSummary, MAX_NAMES, OTHER_NAMES};
+    use crate::metrics::request_metric::{CompletionResult, RequestMetric};
+
+    #[test]
+    fn it_should_correct_latencies_stalled_by_the_server() {
+        let mut summary = MetricsSummary::new(100, None);
+        let mut start = Instant::now();
+        for i in 0..100 {
+            // one request stalls for a second, the others take 1ms every 10ms
+            let duration = if i == 50 {
+                Duration::from_secs(1)
+            } else {
+                Duration::from_millis(1)
+            };
+            summary.record_latency(0, start, duration);
+            start += duration.max(Duration::from_millis(10));
+        }
+
+        summary.update_corrected_latencies(start, false);
+        let corrected = summary.corrected_latencies_us();
+
+        let interval = summary.expected_interval_us();
+        assert!((9_990..=10_010).contains(&interval), "{}", interval);
+        assert_eq!(100, summary.latencies_us.len());
+        assert!(summary.latencies_us.value_at_quantile(0.9) < 2_000);
+        assert!(corrected.len() > 190);
+        assert!(corrected.value_at_quantile(0.9) > 500_000);
+    }
+
+    #[test]
+    fn it_should_rebuild_the_corrected_latencies_at_most_every_interval() {
+        let mut summary = MetricsSummary::new(3, None);
+        let start = Instant::now();
+        summary.record_latency(0, start, Duration::from_millis(1));
+        summary.update_corrected_latencies(start, false);
+        assert_eq!(1, summary.corrected_latencies_us().len());
+
+        summary.record_latency(0, start, Duration::from_millis(1));
+        summary.update_corrected_latencies(start + Duration::from_millis(500), false);
+        assert_eq!(1, summary.corrected_latencies_us().len());
+        summary.update_corrected_latencies(start + Duration::from_secs(1), false);
+        assert_eq!(2, summary.corrected_latencies_us().len());
+
+        summary.record_latency(0, start, Duration::from_millis(1));
+        summary.update_corrected_latencies(start + Duration::from_secs(1), true);
+        assert_eq!(3, summary.corrected_latencies_us().len());
+    }
+
+    #[test]
+    fn it_should_count_skipped_steps_apart_from_the_errors() {
+        let mut summary = MetricsSummary::new(2, None);
+        let mut failed = RequestMetric::new("checkout / POST /orders", 0, false);
+        failed.mark_end(CompletionResult::Error);
+        summary.record(failed);
+        let mut skipped = RequestMetric::new("checkout / GET /orders/1", 0, false);
+        skipped.mark_end(CompletionResult::Skipped);
+        summary.record(skipped);
+
+        assert_eq!(1, summary.errors);
+        assert_eq!(1, summary.skipped);
+        assert!(!summary.by_name.contains_key("checkout / GET /orders/1"));
+        assert!(summary.is_completed());
+    }
+
+    #[test]
+    fn it_should_group_the_names_beyond_the_limit() {
+        let mut summary = MetricsSummary::new(0, None);
+        for i in 0..MAX_NAMES + 50 {
+            let mut metric = RequestMetric::new(&format!("GET /orders/{}", i), 0, false);
+            metric.mark_end(CompletionResult::Ok);
+            summary.record(metric);
+        }
+        let mut metric = RequestMetric::new("GET /orders/0", 0, false);
+        metric.mark_end(CompletionResult::Ok);
+        summary.record(metric);
+
+        assert_eq!(MAX_NAMES + 1, summary.by_name.len());
+        assert_eq!(50, summary.by_name[OTHER_NAMES].ok);
+        assert_eq!(2, summary.by_name["GET /orders/0"].ok);
+    }
+
+    #[test]
+    fn it_should_show_a_bimodal_latency_distribution() {
+        let mut summary = MetricsSummary::new(200, None);
+        for i in 0..200 {
+            let latency_us = if i % 2 == 0 { 10_000 } else { 90_000 };
+            summary.latencies_us.record(latency_us).unwrap();
+        }
+
+        let distribution = summary.latency_distribution(8);
+
+        let counts: Vec<_> = distribution.iter().map(|(_, count)| *count).collect();
+        assert_eq!(vec![100, 0, 0, 0, 0, 0, 0, 100], counts);
+        assert!(distribution[7].0 >= 90_000);
     }
 }
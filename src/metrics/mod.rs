@@ -1,8 +1,22 @@
 
 mod request_metric;
+mod computed_metrics;
 mod metrics_summary;
-mod metrics_layer;
+mod throughput;
+mod latency_window;
+mod error_log;
+mod endpoints;
+mod metrics_recorder;
+mod prometheus_exporter;
+mod otlp_exporter;
+mod sinks;
+mod name_limit;
 
 pub(crate) use request_metric::RequestMetric;
+pub(crate) use endpoints::EndpointColumn;
 pub(crate) use metrics_summary::MetricsSummary;
-pub(crate) use metrics_layer::MetricsLayer;
+pub(crate) use computed_metrics::ComputedMetrics;
+pub(crate) use metrics_recorder::{channel, lossless_channel, MetricsRecorder};
+pub(crate) use prometheus_exporter::serve as serve_prometheus;
+pub(crate) use otlp_exporter::OtlpExporter;
+pub(crate) use sinks::SinkWriter;
@@ -0,0 +1,46 @@
+use std::collections::HashSet;
+
+/// Names with their own metrics, the requests with other names, e.g. unnamed
+/// requests file lines, are grouped under `OTHER_NAMES`
+pub(crate) const MAX_NAMES: usize = 100;
+pub(crate) const OTHER_NAMES: &str = "(other names)";
+
+/// Keeps the number of series bounded where the request name is a label
+/// or a tag (Prometheus, OpenTelemetry, the metric sinks)
+#[derive(Debug, Default)]
+pub(crate) struct NameLimit {
+    names: HashSet<String>,
+}
+
+impl NameLimit {
+    /// `name` if it is one of the first `MAX_NAMES` names seen, `OTHER_NAMES` otherwise
+    pub(crate) fn name<'a>(&mut self, name: &'a str) -> &'a str {
+        if self.names.contains(name) {
+            name
+        } else if self.names.len() < MAX_NAMES {
+            self.names.insert(name.to_owned());
+            name
+        } else {
+            OTHER_NAMES
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NameLimit, MAX_NAMES, OTHER_NAMES};
+
+    #[test]
+    fn it_should_keep_the_first_names_and_group_the_others() {
+        let mut limit = NameLimit::default();
+        let names: Vec<String> = (0..MAX_NAMES + 1)
+            .map(|i| format!("GET /orders/{}", i))
+            .collect();
+        for name in &names[..MAX_NAMES] {
+            assert_eq!(name, limit.name(name));
+        }
+
+        assert_eq!(OTHER_NAMES, limit.name(&names[MAX_NAMES]));
+        assert_eq!("GET /orders/0", limit.name("GET /orders/0"));
+    }
+}
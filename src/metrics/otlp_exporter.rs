@@ -0,0 +1,205 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use anyhow::Context;
+use opentelemetry::metrics::{Counter, Histogram, MeterProvider as _, Unit, UpDownCounter};
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::metrics::MeterProvider;
+use opentelemetry_sdk::{runtime, trace, Resource};
+use tracing::{warn, Metadata, Subscriber};
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use super::name_limit::NameLimit;
+use super::request_metric::{CompletionResult, RequestMetric, RequestState};
+use crate::config::{OtlpParameters, OtlpProtocol};
+
+/// OpenTelemetry instruments updated as the requests complete
+struct OtlpMetrics {
+    requests: Counter<u64>,
+    warmup_requests: Counter<u64>,
+    in_flight: UpDownCounter<i64>,
+    durations: Histogram<f64>,
+    names: NameLimit,
+}
+
+impl OtlpMetrics {
+    fn new(provider: &MeterProvider) -> Self {
+        let meter = provider.meter("parreq");
+        Self {
+            requests: meter
+                .u64_counter("parreq.requests")
+                .with_description("Completed requests")
+                .init(),
+            warmup_requests: meter
+                .u64_counter("parreq.warmup_requests")
+                .with_description(
+                    "Requests completed during the warm-up, left out of the other metrics",
+                )
+                .init(),
+            in_flight: meter
+                .i64_up_down_counter("parreq.requests_in_flight")
+                .with_description("Requests sent and not completed yet")
+                .init(),
+            durations: meter
+                .f64_histogram("parreq.request.duration")
+                .with_description("Duration of the requests")
+                .with_unit(Unit::new("s"))
+                .init(),
+            names: NameLimit::default(),
+        }
+    }
+
+    fn record(&mut self, metric: &RequestMetric) {
+        let name = self.names.name(metric.name()).to_owned();
+        let name = [KeyValue::new("name", name)];
+        match metric.status() {
+            RequestState::InProgress(_) => self.in_flight.add(1, &name),
+            RequestState::Completed(completed) => {
+                self.in_flight.add(-1, &name);
+                if metric.is_warmup() {
+                    self.warmup_requests.add(1, &[]);
+                    return;
+                }
+                // not sent, a previous scenario step failed
+                if let CompletionResult::Skipped = completed.result {
+                    return;
+                }
+                let status = metric
+                    .status_code()
+                    .map_or_else(|| "none".to_owned(), |status| status.to_string());
+                let error_class = metric.error_class().unwrap_or("none").to_owned();
+                self.requests.add(
+                    1,
+                    &[
+                        name[0].clone(),
+                        KeyValue::new("status", status),
+                        KeyValue::new("error_class", error_class),
+                    ],
+                );
+                self.durations
+                    .record((completed.end - completed.start).as_secs_f64(), &name);
+            }
+        }
+    }
+}
+
+/// Ships the request spans and the aggregated request metrics to an
+/// OpenTelemetry collector. `shutdown` has to be called at the end of the run
+/// so the last spans and metrics are sent.
+pub(crate) struct OtlpExporter {
+    meter_provider: MeterProvider,
+    stopping: Arc<AtomicBool>,
+    recorder: JoinHandle<()>,
+}
+
+impl OtlpExporter {
+    /// Must be called within the tokio runtime, the exports run on it.
+    /// Returns the layer sending the request spans.
+    pub(crate) fn start<S>(
+        params: &OtlpParameters,
+        metrics_receiver: Receiver<RequestMetric>,
+    ) -> anyhow::Result<(Self, impl Layer<S>)>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        // the default handler prints on stderr, over the tui
+        opentelemetry::global::set_error_handler(|e| warn!("otlp export error: {}", e))
+            .context("error setting otlp error handler")?;
+        let resource = Resource::new([KeyValue::new("service.name", "parreq")]);
+        let tracer = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(span_exporter(params))
+            .with_trace_config(trace::config().with_resource(resource.clone()))
+            .install_batch(runtime::Tokio)
+            .context("error creating otlp span exporter")?;
+        let meter_provider = opentelemetry_otlp::new_pipeline()
+            .metrics(runtime::Tokio)
+            .with_exporter(metrics_exporter(params))
+            .with_resource(resource)
+            .with_period(params.export_interval)
+            .build()
+            .context("error creating otlp metrics exporter")?;
+
+        let mut metrics = OtlpMetrics::new(&meter_provider);
+        let stopping = Arc::new(AtomicBool::new(false));
+        let recorder_stopping = stopping.clone();
+        let recorder = std::thread::spawn(move || loop {
+            match metrics_receiver.recv_timeout(Duration::from_millis(100)) {
+                Ok(metric) => metrics.record(&metric),
+                Err(RecvTimeoutError::Timeout) if !recorder_stopping.load(Ordering::Relaxed) => {}
+                // the remaining metrics were already sent when stopping
+                Err(_) => {
+                    metrics_receiver
+                        .try_iter()
+                        .for_each(|metric| metrics.record(&metric));
+                    break;
+                }
+            }
+        });
+        let layer = tracing_opentelemetry::layer()
+            .with_tracer(tracer)
+            .with_filter(filter_fn(is_request));
+        Ok((
+            Self {
+                meter_provider,
+                stopping,
+                recorder,
+            },
+            layer,
+        ))
+    }
+
+    /// Records the metrics still in the channel and sends everything left.
+    /// Blocks until the collector answered.
+    pub(crate) fn shutdown(self) -> anyhow::Result<()> {
+        self.stopping.store(true, Ordering::Relaxed);
+        self.recorder
+            .join()
+            .map_err(|_| anyhow::anyhow!("otlp metrics recorder panicked"))?;
+        opentelemetry::global::shutdown_tracer_provider();
+        self.meter_provider
+            .force_flush()
+            .context("error sending the last otlp metrics")?;
+        // the reader refuses to collect once shut down, the metrics were
+        // flushed above so the error it returns is expected
+        let _ = self.meter_provider.shutdown();
+        Ok(())
+    }
+}
+
+/// Only the request spans (and their events) are exported
+fn is_request(metadata: &Metadata<'_>) -> bool {
+    metadata.target() == "parreq::request"
+}
+
+fn span_exporter(params: &OtlpParameters) -> opentelemetry_otlp::SpanExporterBuilder {
+    match params.protocol {
+        OtlpProtocol::Grpc => opentelemetry_otlp::new_exporter()
+            .tonic()
+            .with_endpoint(&params.endpoint)
+            .into(),
+        OtlpProtocol::Http => opentelemetry_otlp::new_exporter()
+            .http()
+            .with_endpoint(&params.endpoint)
+            .into(),
+    }
+}
+
+fn metrics_exporter(params: &OtlpParameters) -> opentelemetry_otlp::MetricsExporterBuilder {
+    match params.protocol {
+        OtlpProtocol::Grpc => opentelemetry_otlp::new_exporter()
+            .tonic()
+            .with_endpoint(&params.endpoint)
+            .into(),
+        OtlpProtocol::Http => opentelemetry_otlp::new_exporter()
+            .http()
+            .with_endpoint(&params.endpoint)
+            .into(),
+    }
+}
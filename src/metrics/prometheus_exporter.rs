@@ -0,0 +1,202 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Context;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use tracing::warn;
+
+use super::name_limit::NameLimit;
+use super::request_metric::{CompletionResult, RequestMetric, RequestState};
+
+/// Connections are served one at a time: a client that stops sending or
+/// reading only holds the next scrapes back this long
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Request metrics in the Prometheus format, updated as the requests complete
+pub(crate) struct PrometheusExporter {
+    registry: Registry,
+    requests: IntCounterVec,
+    warmup_requests: IntCounter,
+    in_flight: IntGaugeVec,
+    durations: HistogramVec,
+    names: Mutex<NameLimit>,
+}
+
+impl PrometheusExporter {
+    fn new() -> Self {
+        let registry =
+            Registry::new_custom(Some("parreq".to_owned()), None).expect("valid prometheus prefix");
+        let requests = IntCounterVec::new(
+            Opts::new("requests_total", "Completed requests"),
+            &["name", "status", "error_class"],
+        )
+        .expect("valid prometheus metric");
+        let warmup_requests = IntCounter::new(
+            "warmup_requests_total",
+            "Requests completed during the warm-up, left out of the other metrics",
+        )
+        .expect("valid prometheus metric");
+        let in_flight = IntGaugeVec::new(
+            Opts::new("requests_in_flight", "Requests sent and not completed yet"),
+            &["name"],
+        )
+        .expect("valid prometheus metric");
+        let durations = HistogramVec::new(
+            HistogramOpts::new("request_duration_seconds", "Duration of the requests"),
+            &["name"],
+        )
+        .expect("valid prometheus metric");
+        for collector in [
+            Box::new(requests.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(warmup_requests.clone()),
+            Box::new(in_flight.clone()),
+            Box::new(durations.clone()),
+        ] {
+            registry
+                .register(collector)
+                .expect("prometheus metrics registered once");
+        }
+        Self {
+            registry,
+            requests,
+            warmup_requests,
+            in_flight,
+            durations,
+            names: Mutex::default(),
+        }
+    }
+
+    fn record(&self, metric: &RequestMetric) {
+        let name = self
+            .names
+            .lock()
+            .expect("prometheus names poisoned")
+            .name(metric.name());
+        match metric.status() {
+            RequestState::InProgress(_) => self.in_flight.with_label_values(&[name]).inc(),
+            RequestState::Completed(completed) => {
+                self.in_flight.with_label_values(&[name]).dec();
+                if metric.is_warmup() {
+                    self.warmup_requests.inc();
+                    return;
+                }
+                // not sent, a previous scenario step failed
+                if let CompletionResult::Skipped = completed.result {
+                    return;
+                }
+                let status = metric
+                    .status_code()
+                    .map_or_else(|| "none".to_owned(), |status| status.to_string());
+                let error_class = metric.error_class().unwrap_or("none");
+                self.requests
+                    .with_label_values(&[name, &status, error_class])
+                    .inc();
+                self.durations
+                    .with_label_values(&[name])
+                    .observe((completed.end - completed.start).as_secs_f64());
+            }
+        }
+    }
+
+    fn render(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("prometheus text encoding");
+        buffer
+    }
+}
+
+/// Serves the metrics received from `metrics_receiver` on `http://{addr}/metrics`
+/// until the end of the run
+pub(crate) fn serve(
+    addr: SocketAddr,
+    metrics_receiver: Receiver<RequestMetric>,
+) -> anyhow::Result<()> {
+    let listener =
+        TcpListener::bind(addr).with_context(|| format!("error listening on {}", addr))?;
+    let exporter = Arc::new(PrometheusExporter::new());
+
+    let recorder = exporter.clone();
+    std::thread::spawn(move || {
+        for metric in metrics_receiver {
+            recorder.record(&metric);
+        }
+    });
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream
+                .map_err(anyhow::Error::from)
+                .and_then(|stream| respond(stream, &exporter));
+            if let Err(e) = result {
+                warn!("error serving prometheus metrics: {:#}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Minimal http/1.1 answer, enough for a scraper
+fn respond(mut stream: TcpStream, exporter: &PrometheusExporter) -> anyhow::Result<()> {
+    stream.set_read_timeout(Some(CONNECTION_TIMEOUT))?;
+    stream.set_write_timeout(Some(CONNECTION_TIMEOUT))?;
+    let mut request_line = String::new();
+    let mut reader = BufReader::new(stream.try_clone()?);
+    reader.read_line(&mut request_line)?;
+    // the headers aren't used, they are only read to be able to answer
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+    let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+    let (status, content_type, body) = if path == "/metrics" || path.starts_with("/metrics?") {
+        (
+            "200 OK",
+            TextEncoder::new().format_type().to_owned(),
+            exporter.render(),
+        )
+    } else {
+        (
+            "404 Not Found",
+            "text/plain".to_owned(),
+            b"not found\n".to_vec(),
+        )
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PrometheusExporter;
+    use crate::metrics::request_metric::{CompletionResult, RequestMetric};
+
+    #[test]
+    fn it_should_count_completed_requests() {
+        let exporter = PrometheusExporter::new();
+        let mut metric = RequestMetric::new("", 0, false);
+        exporter.record(&metric);
+        metric.mark_end(CompletionResult::Ok);
+        exporter.record(&metric);
+
+        let rendered = String::from_utf8(exporter.render()).unwrap();
+
+        assert!(rendered
+            .contains(r#"parreq_requests_total{error_class="none",name="",status="none"} 1"#));
+        assert!(rendered.contains(r#"parreq_requests_in_flight{name=""} 0"#));
+        assert!(rendered.contains(r#"parreq_request_duration_seconds_count{name=""} 1"#));
+    }
+}
@@ -1,6 +1,9 @@
 
+use std::time::SystemTime;
+
 use tokio::time::Instant;
-use tracing::field::{self, Visit};
+
+use crate::request::{ErrorClass, RequestError, RequestOutcome};
 
 #[derive(Debug, Clone)]
 pub(crate) struct InProgressState {
@@ -11,11 +14,15 @@ pub(crate) struct InProgressState {
 pub(crate) enum CompletionResult {
     Ok,
     Error,
+    /// Not sent because a previous scenario step failed, neither ok nor an error
+    Skipped,
 }
 #[derive(Debug, Clone)]
 pub(crate) struct CompletedState {
     pub(crate) start: Instant,
     pub(crate) end: Instant,
+    /// Wall clock time of the end, for the metric sinks
+    pub(crate) completed_at: SystemTime,
     pub(crate) result: CompletionResult
 }
 
@@ -26,31 +33,81 @@ pub(crate) enum RequestState {
 }
 #[derive(Debug, Clone)]
 pub(crate) struct RequestMetric {
-    request_id: u64,
+    name: String,
     executor_id: u64,
-    task_in_executor: u64,
+    status_code: Option<u16>,
+    error_class: Option<ErrorClass>,
+    attempts: u64,
+    first_attempt_error_class: Option<ErrorClass>,
+    warmup: bool,
     status: RequestState,
-}
-impl Visit for RequestMetric {
-    fn record_u64(&mut self, field: &field::Field, value: u64) {
-        match field.name() {
-            "metric_executor_id" => self.executor_id = value,
-            "metric_task_in_executor" => self.task_in_executor = value,
-            "metric_request_number" => self.request_id = value,
-            &_ => {}
-        }
-    }
-    fn record_debug(&mut self, _field: &field::Field, _value: &dyn std::fmt::Debug) {}
+    /// Message of the error of a failed request
+    error_message: Option<String>,
+    /// Only set for failed requests, the name is enough otherwise
+    url: Option<String>,
+    /// Succeeded, but an `extract` expression found no value
+    extraction_missed: bool,
+    /// W3C trace id sent in the `traceparent` header, if any
+    trace_id: Option<String>,
 }
 impl RequestMetric {
-    pub(super) fn new() -> Self {
+    /// Metric of a request about to be sent
+    pub(crate) fn new(name: &str, executor_id: usize, warmup: bool) -> Self {
         RequestMetric {
-            task_in_executor: 0,
-            executor_id: 0,
-            request_id: 0,
+            name: name.to_owned(),
+            executor_id: executor_id as u64,
+            status_code: None,
+            error_class: None,
+            attempts: 1,
+            first_attempt_error_class: None,
+            warmup,
             status: RequestState::InProgress(InProgressState {
                 start: Instant::now(),
             }),
+            error_message: None,
+            url: None,
+            extraction_missed: false,
+            trace_id: None,
+        }
+    }
+
+    /// Status code of the last response, also set for failed requests
+    pub(crate) fn set_status_code(&mut self, status_code: u16) {
+        self.status_code = Some(status_code);
+    }
+
+    pub(crate) fn set_attempts(&mut self, attempts: usize) {
+        self.attempts = attempts as u64;
+    }
+
+    pub(crate) fn set_url(&mut self, url: String) {
+        self.url = Some(url);
+    }
+
+    pub(crate) fn set_trace_id(&mut self, trace_id: String) {
+        self.trace_id = Some(trace_id);
+    }
+
+    pub(crate) fn set_first_attempt_error(&mut self, class: ErrorClass) {
+        self.first_attempt_error_class = Some(class);
+    }
+
+    pub(crate) fn complete(&mut self, result: &Result<RequestOutcome, RequestError>) {
+        match result {
+            Ok(outcome) => {
+                self.status_code = Some(outcome.status.as_u16());
+                self.extraction_missed = outcome.extraction_error.is_some();
+                self.mark_end(CompletionResult::Ok);
+            }
+            Err(e) if e.class == ErrorClass::Skipped => {
+                self.error_message = Some(e.msg.clone());
+                self.mark_end(CompletionResult::Skipped);
+            }
+            Err(e) => {
+                self.error_class = Some(e.class);
+                self.error_message = Some(e.msg.clone());
+                self.mark_end(CompletionResult::Error);
+            }
         }
     }
 
@@ -59,6 +116,7 @@ impl RequestMetric {
             self.status = RequestState::Completed(CompletedState {
                 start: in_progress_state.start,
                 end: Instant::now(),
+                completed_at: SystemTime::now(),
                 result
             });
         }
@@ -67,4 +125,115 @@ impl RequestMetric {
     pub fn status(&self) -> &RequestState {
         &self.status
     }
+
+    pub fn executor_id(&self) -> u64 {
+        self.executor_id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Response status code, if a response was received
+    pub fn status_code(&self) -> Option<u16> {
+        self.status_code
+    }
+
+    /// Class of the failure (timeout, connect, status...) for failed requests
+    pub fn error_class(&self) -> Option<&'static str> {
+        self.error_class.map(|class| class.as_str())
+    }
+
+    pub fn error_message(&self) -> Option<&str> {
+        self.error_message.as_deref()
+    }
+
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    /// Number of attempts sent, retries included
+    pub fn attempts(&self) -> u64 {
+        self.attempts
+    }
+
+    /// W3C trace id sent in the `traceparent` header, to find the server traces
+    pub fn trace_id(&self) -> Option<&str> {
+        self.trace_id.as_deref()
+    }
+
+    pub fn extraction_missed(&self) -> bool {
+        self.extraction_missed
+    }
+
+    /// Sent during the warm-up, left out of the statistics
+    pub fn is_warmup(&self) -> bool {
+        self.warmup
+    }
+
+    /// Whether the first attempt failed, even if a retry succeeded afterwards
+    pub fn first_attempt_failed(&self) -> bool {
+        self.first_attempt_error_class.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CompletionResult, RequestMetric, RequestState};
+    use crate::request::{ErrorClass, RequestError, RequestOutcome};
+    use crate::template::Variables;
+
+    #[test]
+    fn it_should_complete_with_the_error_class_and_status() {
+        let mut metric = RequestMetric::new("GET /orders", 3, false);
+        metric.set_first_attempt_error(ErrorClass::Timeout);
+        metric.set_attempts(2);
+        metric.set_status_code(503);
+        metric.complete(&Err(RequestError::new(
+            ErrorClass::Status,
+            "status code error".to_owned(),
+        )));
+
+        assert!(matches!(metric.status(), RequestState::Completed(_)));
+        assert_eq!(Some("status"), metric.error_class());
+        assert_eq!(Some("status code error"), metric.error_message());
+        assert_eq!(Some(503), metric.status_code());
+        assert_eq!(2, metric.attempts());
+        assert!(metric.first_attempt_failed());
+    }
+
+    #[test]
+    fn it_should_count_an_extraction_miss_as_ok() {
+        let mut metric = RequestMetric::new("GET /orders", 0, false);
+        metric.complete(&Ok(RequestOutcome {
+            status: reqwest::StatusCode::OK,
+            variables: Variables::new(),
+            extraction_error: Some(RequestError::new(
+                ErrorClass::Extraction,
+                "no value found".to_owned(),
+            )),
+        }));
+
+        let RequestState::Completed(completed) = metric.status() else {
+            panic!("request not completed");
+        };
+        assert!(matches!(completed.result, CompletionResult::Ok));
+        assert_eq!(None, metric.error_class());
+        assert!(metric.extraction_missed());
+    }
+
+    #[test]
+    fn it_should_complete_a_skipped_step_without_an_error() {
+        let mut metric = RequestMetric::new("checkout / GET /orders", 0, false);
+        metric.complete(&Err(RequestError::new(
+            ErrorClass::Skipped,
+            "previous step failed".to_owned(),
+        )));
+
+        let RequestState::Completed(completed) = metric.status() else {
+            panic!("request not completed");
+        };
+        assert!(matches!(completed.result, CompletionResult::Skipped));
+        assert_eq!(None, metric.error_class());
+    }
 }
@@ -0,0 +1,359 @@
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use tracing::warn;
+
+use super::name_limit::NameLimit;
+use super::request_metric::{CompletionResult, RequestMetric, RequestState};
+use crate::config::{MetricSinkKind, MetricSinkParameters};
+
+/// Lines sent at once, whatever the flush interval
+const MAX_BATCH: usize = 5_000;
+/// Keeps udp datagrams under the usual MTU
+const MAX_DATAGRAM: usize = 1_400;
+
+/// What the sinks know of a completed request
+struct CompletedRequest<'a> {
+    tags: Vec<(&'a str, &'a str)>,
+    duration: Duration,
+    attempts: u64,
+    success: bool,
+    timestamp: SystemTime,
+    /// A field rather than a tag, each request has its own
+    trace_id: Option<&'a str>,
+}
+
+/// Format of the lines written for each completed request
+trait MetricSink: Send {
+    fn format(&self, request: &CompletedRequest, batch: &mut Vec<String>);
+    fn send(&self, batch: &[String]) -> anyhow::Result<()>;
+}
+
+enum Transport {
+    /// Posts from the writer thread on the tokio runtime
+    Http {
+        runtime: tokio::runtime::Handle,
+        client: reqwest::Client,
+        url: reqwest::Url,
+        token: Option<String>,
+    },
+    Udp(UdpSocket),
+}
+
+impl Transport {
+    fn udp(address: &str) -> anyhow::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0").context("error binding udp socket")?;
+        socket
+            .connect(address)
+            .with_context(|| format!("invalid udp address {}", address))?;
+        Ok(Transport::Udp(socket))
+    }
+
+    fn send(&self, lines: &[String]) -> anyhow::Result<()> {
+        match self {
+            Transport::Http {
+                runtime,
+                client,
+                url,
+                token,
+            } => {
+                let mut request = client
+                    .post(url.clone())
+                    .header(reqwest::header::CONTENT_TYPE, "text/plain; charset=utf-8")
+                    .body(lines.join("\n"));
+                if let Some(token) = token {
+                    request =
+                        request.header(reqwest::header::AUTHORIZATION, format!("Token {}", token));
+                }
+                runtime
+                    .block_on(request.send())
+                    .and_then(|response| response.error_for_status())
+                    .with_context(|| format!("error posting metrics to {}", url))?;
+            }
+            // several lines per datagram, none split across two
+            Transport::Udp(socket) => {
+                let mut datagram = String::new();
+                for line in lines {
+                    if !datagram.is_empty() && datagram.len() + 1 + line.len() > MAX_DATAGRAM {
+                        socket.send(datagram.as_bytes())?;
+                        datagram.clear();
+                    }
+                    if !datagram.is_empty() {
+                        datagram.push('\n');
+                    }
+                    datagram.push_str(line);
+                }
+                if !datagram.is_empty() {
+                    socket.send(datagram.as_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// InfluxDB line protocol, one `parreq_request` point per request
+struct InfluxSink {
+    transport: Transport,
+}
+
+impl MetricSink for InfluxSink {
+    fn format(&self, request: &CompletedRequest, batch: &mut Vec<String>) {
+        let mut line = "parreq_request".to_owned();
+        for (key, value) in &request.tags {
+            line.push(',');
+            line.push_str(&influx_escape(key));
+            line.push('=');
+            line.push_str(&influx_escape(value));
+        }
+        let timestamp = request
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        line.push_str(&format!(
+            " duration_ms={},attempts={}i,success={}",
+            request.duration.as_secs_f64() * 1000.0,
+            request.attempts,
+            request.success,
+        ));
+        if let Some(trace_id) = request.trace_id {
+            line.push_str(&format!(",trace_id=\"{}\"", trace_id));
+        }
+        line.push_str(&format!(" {}", timestamp));
+        batch.push(line);
+    }
+
+    fn send(&self, batch: &[String]) -> anyhow::Result<()> {
+        self.transport.send(batch)
+    }
+}
+
+fn influx_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+        .replace('\n', "\\n")
+}
+
+/// StatsD counter and timer, tagged the DogStatsD way (`|#key:value`)
+struct StatsdSink {
+    prefix: String,
+    transport: Transport,
+}
+
+impl MetricSink for StatsdSink {
+    fn format(&self, request: &CompletedRequest, batch: &mut Vec<String>) {
+        let tags = request
+            .tags
+            .iter()
+            .map(|(key, value)| format!("{}:{}", statsd_escape(key), statsd_escape(value)))
+            .collect::<Vec<_>>()
+            .join(",");
+        batch.push(format!("{}.requests:1|c|#{}", self.prefix, tags));
+        batch.push(format!(
+            "{}.request.duration:{}|ms|#{}",
+            self.prefix,
+            request.duration.as_secs_f64() * 1000.0,
+            tags
+        ));
+    }
+
+    fn send(&self, batch: &[String]) -> anyhow::Result<()> {
+        self.transport.send(batch)
+    }
+}
+
+fn statsd_escape(value: &str) -> String {
+    value.replace([',', '|', '#', '\n'], "_")
+}
+
+/// Writes the completed requests to a sink from its own thread, in batches.
+/// `shutdown` sends what is left at the end of the run.
+pub(crate) struct SinkWriter {
+    stopping: Arc<AtomicBool>,
+    writer: JoinHandle<()>,
+}
+
+impl SinkWriter {
+    /// Must be called within the tokio runtime
+    pub(crate) fn start(
+        params: &MetricSinkParameters,
+        run_id: &str,
+        metrics_receiver: Receiver<RequestMetric>,
+    ) -> anyhow::Result<Self> {
+        let flush_interval = params.flush_interval;
+        let sink: Box<dyn MetricSink> = match &params.kind {
+            MetricSinkKind::InfluxdbHttp { url, token } => Box::new(InfluxSink {
+                transport: Transport::Http {
+                    runtime: tokio::runtime::Handle::current(),
+                    // a slow server delays the next batches by one interval at most
+                    client: reqwest::Client::builder()
+                        .connect_timeout(flush_interval)
+                        .timeout(flush_interval)
+                        .build()
+                        .context("error creating influxdb http client")?,
+                    url: url
+                        .parse()
+                        .with_context(|| format!("invalid influxdb url {}", url))?,
+                    token: token.clone(),
+                },
+            }),
+            MetricSinkKind::InfluxdbUdp { address } => Box::new(InfluxSink {
+                transport: Transport::udp(address)?,
+            }),
+            MetricSinkKind::Statsd { address, prefix } => Box::new(StatsdSink {
+                prefix: prefix.clone(),
+                transport: Transport::udp(address)?,
+            }),
+        };
+        let mut writer = BatchWriter {
+            sink,
+            run_id: run_id.to_owned(),
+            tags: params
+                .tags
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+            batch: Vec::new(),
+            names: NameLimit::default(),
+        };
+        let stopping = Arc::new(AtomicBool::new(false));
+        let writer_stopping = stopping.clone();
+        let writer = std::thread::spawn(move || {
+            let mut next_flush = Instant::now() + flush_interval;
+            loop {
+                let timeout = next_flush
+                    .saturating_duration_since(Instant::now())
+                    .min(Duration::from_millis(100));
+                match metrics_receiver.recv_timeout(timeout) {
+                    Ok(metric) => writer.add(&metric),
+                    Err(RecvTimeoutError::Timeout) if !writer_stopping.load(Ordering::Relaxed) => {}
+                    // the remaining metrics were already sent when stopping
+                    Err(_) => {
+                        metrics_receiver
+                            .try_iter()
+                            .for_each(|metric| writer.add(&metric));
+                        writer.flush();
+                        break;
+                    }
+                }
+                if writer.batch.len() >= MAX_BATCH || Instant::now() >= next_flush {
+                    writer.flush();
+                    next_flush = Instant::now() + flush_interval;
+                }
+            }
+        });
+        Ok(Self { stopping, writer })
+    }
+
+    /// Blocks until the last batch is sent
+    pub(crate) fn shutdown(self) -> anyhow::Result<()> {
+        self.stopping.store(true, Ordering::Relaxed);
+        self.writer
+            .join()
+            .map_err(|_| anyhow::anyhow!("metric sink writer panicked"))
+    }
+}
+
+struct BatchWriter {
+    sink: Box<dyn MetricSink>,
+    run_id: String,
+    tags: Vec<(String, String)>,
+    batch: Vec<String>,
+    names: NameLimit,
+}
+
+impl BatchWriter {
+    fn add(&mut self, metric: &RequestMetric) {
+        let RequestState::Completed(completed) = metric.status() else {
+            return;
+        };
+        // skipped scenario steps weren't sent
+        if metric.is_warmup() || matches!(completed.result, CompletionResult::Skipped) {
+            return;
+        }
+        let status = metric
+            .status_code()
+            .map_or_else(|| "none".to_owned(), |status| status.to_string());
+        let mut tags = vec![
+            ("run_id", self.run_id.as_str()),
+            ("name", self.names.name(metric.name())),
+            ("status", &status),
+            ("error_class", metric.error_class().unwrap_or("none")),
+        ];
+        tags.extend(
+            self.tags
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str())),
+        );
+        // empty tags aren't valid in the line protocol
+        tags.retain(|(_, value)| !value.is_empty());
+        let request = CompletedRequest {
+            tags,
+            duration: completed.end - completed.start,
+            attempts: metric.attempts(),
+            success: matches!(completed.result, CompletionResult::Ok),
+            timestamp: completed.completed_at,
+            trace_id: metric.trace_id(),
+        };
+        self.sink.format(&request, &mut self.batch);
+    }
+
+    /// A failed batch is dropped, the run goes on
+    fn flush(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+        if let Err(e) = self.sink.send(&self.batch) {
+            warn!("error sending metrics: {:#}", e);
+        }
+        self.batch.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{CompletedRequest, InfluxSink, MetricSink, StatsdSink, Transport};
+
+    #[test]
+    fn it_should_format_influx_and_statsd_lines() {
+        let request = CompletedRequest {
+            tags: vec![("name", "GET http://localhost/a,b"), ("status", "200")],
+            duration: Duration::from_millis(12),
+            attempts: 1,
+            success: true,
+            timestamp: UNIX_EPOCH + Duration::from_secs(1),
+            trace_id: Some("4bf92f3577b34da6a3ce929d0e0e4736"),
+        };
+        let transport = || Transport::udp("127.0.0.1:9").unwrap();
+        let mut batch = Vec::new();
+        InfluxSink {
+            transport: transport(),
+        }
+        .format(&request, &mut batch);
+        StatsdSink {
+            prefix: "parreq".to_owned(),
+            transport: transport(),
+        }
+        .format(&request, &mut batch);
+
+        assert_eq!(
+            vec![
+                r#"parreq_request,name=GET\ http://localhost/a\,b,status=200 duration_ms=12,attempts=1i,success=true,trace_id="4bf92f3577b34da6a3ce929d0e0e4736" 1000000000"#,
+                "parreq.requests:1|c|#name:GET http://localhost/a_b,status:200",
+                "parreq.request.duration:12|ms|#name:GET http://localhost/a_b,status:200",
+            ],
+            batch
+        );
+    }
+}
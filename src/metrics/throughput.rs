@@ -0,0 +1,119 @@
+use tokio::time::Instant;
+
+/// Completed requests per second of the run, in one second buckets. Only
+/// the seconds already over are reported, the current one is still filling.
+#[derive(Debug)]
+pub(crate) struct Throughput {
+    start: Instant,
+    completed: Vec<u64>,
+    errors: Vec<u64>,
+}
+
+impl Throughput {
+    pub fn new(start: Instant) -> Self {
+        Self {
+            start,
+            completed: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, end: Instant, error: bool) {
+        let second = end.saturating_duration_since(self.start).as_secs() as usize;
+        if self.completed.len() <= second {
+            self.completed.resize(second + 1, 0);
+            self.errors.resize(second + 1, 0);
+        }
+        self.completed[second] += 1;
+        if error {
+            self.errors[second] += 1;
+        }
+    }
+
+    /// Seconds over at `now`
+    fn elapsed_secs(&self, now: Instant) -> usize {
+        now.saturating_duration_since(self.start).as_secs() as usize
+    }
+
+    fn over<'a>(&self, buckets: &'a [u64], now: Instant) -> &'a [u64] {
+        &buckets[..buckets.len().min(self.elapsed_secs(now))]
+    }
+
+    /// Requests completed during the last second over
+    pub fn current_rps(&self, now: Instant) -> u64 {
+        match self.elapsed_secs(now) {
+            0 => 0,
+            elapsed => self.completed.get(elapsed - 1).copied().unwrap_or(0),
+        }
+    }
+
+    pub fn average_rps(&self, now: Instant) -> f64 {
+        match self.elapsed_secs(now) {
+            0 => 0f64,
+            elapsed => self.over(&self.completed, now).iter().sum::<u64>() as f64 / elapsed as f64,
+        }
+    }
+
+    pub fn peak_rps(&self, now: Instant) -> u64 {
+        self.over(&self.completed, now)
+            .iter()
+            .copied()
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// (elapsed seconds, requests per second) of every second over
+    pub fn completed_points(&self, now: Instant) -> Vec<(f64, f64)> {
+        self.points(&self.completed, now)
+    }
+
+    pub fn error_points(&self, now: Instant) -> Vec<(f64, f64)> {
+        self.points(&self.errors, now)
+    }
+
+    fn points(&self, buckets: &[u64], now: Instant) -> Vec<(f64, f64)> {
+        let mut points: Vec<_> = (0..self.elapsed_secs(now))
+            .map(|second| {
+                let rps = buckets.get(second).copied().unwrap_or(0);
+                // plotted at the end of its second
+                ((second + 1) as f64, rps as f64)
+            })
+            .collect();
+        points.insert(0, (0f64, 0f64));
+        points
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::Throughput;
+
+    #[test]
+    fn it_should_count_the_requests_of_each_second_over() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut throughput = Throughput::new(start);
+        for (end, error) in [(100, false), (900, true), (1200, false), (3500, false)] {
+            throughput.record(at(end), error);
+        }
+
+        let now = at(3600);
+
+        assert_eq!(0, throughput.current_rps(now));
+        assert_eq!(2, throughput.peak_rps(now));
+        assert_eq!(1f64, throughput.average_rps(now));
+        assert_eq!(
+            vec![(0f64, 0f64), (1f64, 2f64), (2f64, 1f64), (3f64, 0f64)],
+            throughput.completed_points(now)
+        );
+        assert_eq!(
+            vec![(0f64, 0f64), (1f64, 1f64), (2f64, 0f64), (3f64, 0f64)],
+            throughput.error_points(now)
+        );
+        assert_eq!(1, throughput.current_rps(at(4000)));
+    }
+}
@@ -0,0 +1,186 @@
+use std::sync::Arc;
+
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::task::TaskParameters;
+
+/// Tasks of all the iterations. Each iteration is made of one task per
+/// `requests` and `scenarios` entry, followed by the requests file lines.
+///
+/// Entries are taken in order, unless a weight is set on any of them: they
+/// are then drawn at random according to their weights. The draws only
+/// depend on the seed, so every clone yields the same tasks.
+#[derive(Clone)]
+pub(crate) struct TaskMix<F> {
+    tasks: Arc<[TaskParameters]>,
+    weights: Option<WeightedIndex<u32>>,
+    rng: StdRng,
+    /// Requests file tasks, read again from the start on every iteration
+    file: Option<F>,
+    file_in_iteration: Option<F>,
+    /// Entries already taken in the current iteration
+    taken: usize,
+    remaining_iterations: usize,
+}
+
+impl<F> TaskMix<F>
+where
+    F: Iterator<Item = TaskParameters> + Clone,
+{
+    /// `weights` are the entries weights when any is set
+    pub(crate) fn new(
+        tasks: Vec<TaskParameters>,
+        weights: Option<Vec<u32>>,
+        file: Option<F>,
+        iterations: usize,
+        seed: u64,
+    ) -> Self {
+        let weights = weights.map(|weights| {
+            WeightedIndex::new(weights).expect("weights checked when loading the configuration")
+        });
+        Self {
+            tasks: tasks.into(),
+            weights,
+            rng: StdRng::seed_from_u64(seed),
+            file_in_iteration: file.clone(),
+            file,
+            taken: 0,
+            remaining_iterations: iterations,
+        }
+    }
+
+    /// Number of requests the tasks still to be taken will send, from the
+    /// same draws. `file_requests` is the number of lines of the requests file.
+    pub(crate) fn expected_requests(&self, file_requests: usize) -> usize {
+        let mut rng = self.rng.clone();
+        let mut taken = self.taken;
+        let mut requests = 0;
+        for _ in 0..self.remaining_iterations {
+            while taken < self.tasks.len() {
+                let index = match &self.weights {
+                    Some(weights) => weights.sample(&mut rng),
+                    None => taken,
+                };
+                taken += 1;
+                requests += self.tasks[index].requests();
+            }
+            requests += file_requests;
+            taken = 0;
+        }
+        requests
+    }
+}
+
+impl<F> Iterator for TaskMix<F>
+where
+    F: Iterator<Item = TaskParameters> + Clone,
+{
+    type Item = TaskParameters;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.remaining_iterations > 0 {
+            if self.taken < self.tasks.len() {
+                let index = match &self.weights {
+                    Some(weights) => weights.sample(&mut self.rng),
+                    None => self.taken,
+                };
+                self.taken += 1;
+                return Some(self.tasks[index].clone());
+            }
+            if let Some(task) = self.file_in_iteration.as_mut().and_then(Iterator::next) {
+                return Some(task);
+            }
+            self.remaining_iterations -= 1;
+            self.taken = 0;
+            self.file_in_iteration = self.file.clone();
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter::Empty;
+    use std::sync::Arc;
+
+    use super::TaskMix;
+    use crate::config::ScenarioParameters;
+    use crate::task::TaskParameters;
+
+    fn names(mix: TaskMix<Empty<TaskParameters>>) -> Vec<String> {
+        mix.map(|task| match task {
+            TaskParameters::Scenario(scenario) => scenario.name.clone(),
+            TaskParameters::Request(req) => req.display_name(),
+            TaskParameters::Failed(error) => error.msg.clone(),
+        })
+        .collect()
+    }
+
+    fn scenario(name: &str, steps: usize) -> TaskParameters {
+        let step = serde_json::from_str(r#"{"url": "http://localhost/", "action": "GET"}"#);
+        TaskParameters::Scenario(Arc::new(ScenarioParameters {
+            name: name.to_owned(),
+            weight: None,
+            feeder: None,
+            steps: vec![step.unwrap(); steps],
+        }))
+    }
+
+    fn tasks() -> Vec<TaskParameters> {
+        ["read", "write"]
+            .into_iter()
+            .map(|name| {
+                TaskParameters::Scenario(Arc::new(ScenarioParameters {
+                    name: name.to_owned(),
+                    weight: None,
+                    feeder: None,
+                    steps: vec![],
+                }))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_should_cycle_without_weights() {
+        let mix = TaskMix::new(tasks(), None, None, 2, 0);
+
+        assert_eq!(vec!["read", "write", "read", "write"], names(mix));
+    }
+
+    #[test]
+    fn it_should_take_every_entry_and_file_line_once_per_iteration() {
+        let tasks = vec![scenario("read", 1), scenario("write", 1)];
+        let file = vec![scenario("line", 1); 3];
+        let mix = TaskMix::new(tasks, None, Some(file.into_iter()), 4, 0);
+
+        assert_eq!(4 * (2 + 3), mix.clone().count());
+        // the progress total, the run is only completed when it is reached
+        assert_eq!(4 * (2 + 3), mix.expected_requests(3));
+    }
+
+    #[test]
+    fn it_should_follow_weights_with_the_same_draws_for_a_seed() {
+        let mix = TaskMix::new(tasks(), Some(vec![9, 1]), None, 1000, 42);
+        let drawn = names(mix.clone());
+        let reads = drawn.iter().filter(|name| *name == "read").count();
+
+        assert_eq!(2000, drawn.len());
+        assert!((1700..1900).contains(&reads), "{} reads", reads);
+        assert_eq!(drawn, names(mix));
+    }
+
+    #[test]
+    fn it_should_count_the_requests_of_the_drawn_scenarios() {
+        let tasks = vec![scenario("read", 1), scenario("write", 3)];
+        let mix: TaskMix<Empty<TaskParameters>> =
+            TaskMix::new(tasks, Some(vec![9, 1]), None, 100, 42);
+        let sent: usize = mix.clone().map(|task| task.requests()).sum();
+
+        assert_eq!(sent, mix.expected_requests(0));
+        assert_eq!(sent + 100 * 5, mix.expected_requests(5));
+        // 4 requests per iteration when each scenario runs once
+        assert_ne!(400, sent);
+    }
+}
@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::Rng;
+use tokio::time::Instant;
+
+use crate::config::ThinkTime;
+
+impl ThinkTime {
+    pub(crate) fn sample(&self, rng: &mut impl Rng) -> Duration {
+        match *self {
+            ThinkTime::Fixed { duration } => duration,
+            ThinkTime::Uniform { min, max } => rng.gen_range(min..=max),
+            ThinkTime::Exponential { mean } => {
+                // inverse of the cumulative distribution, 1 - u never is 0
+                let u: f64 = rng.gen();
+                mean.mul_f64(-(1f64 - u).ln())
+            }
+        }
+    }
+}
+
+/// Wait of an executor between two of its tasks, so virtual users behave
+/// like humans rather than tight loops
+pub(crate) struct Pacing {
+    think_time: Option<ThinkTime>,
+    /// Interval between the starts of two tasks
+    interval: Option<Duration>,
+    rng: StdRng,
+}
+
+impl Pacing {
+    pub(crate) fn new(
+        think_time: Option<ThinkTime>,
+        interval: Option<Duration>,
+        rng: StdRng,
+    ) -> Self {
+        Self {
+            think_time,
+            interval,
+            rng,
+        }
+    }
+
+    /// Delay before the next task, the task just completed having started at
+    /// `task_start`. With both a think time and an interval, the longest wins.
+    pub(crate) fn delay(&mut self, task_start: Instant) -> Duration {
+        let think_time = self
+            .think_time
+            .as_ref()
+            .map_or(Duration::ZERO, |think_time| {
+                think_time.sample(&mut self.rng)
+            });
+        let interval_left = self.interval.map_or(Duration::ZERO, |interval| {
+            (task_start + interval).saturating_duration_since(Instant::now())
+        });
+        think_time.max(interval_left)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use tokio::time::Instant;
+
+    use super::Pacing;
+    use crate::config::ThinkTime;
+
+    #[test]
+    fn it_should_sample_think_times() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (min, max) = (Duration::from_millis(100), Duration::from_millis(200));
+        let uniform = ThinkTime::Uniform { min, max };
+        assert!((0..100)
+            .map(|_| uniform.sample(&mut rng))
+            .all(|delay| (min..=max).contains(&delay)));
+
+        let mean = Duration::from_millis(100);
+        let exponential = ThinkTime::Exponential { mean };
+        let total: Duration = (0..10_000).map(|_| exponential.sample(&mut rng)).sum();
+        let average = total / 10_000;
+        assert!(
+            (Duration::from_millis(90)..Duration::from_millis(110)).contains(&average),
+            "{:?}",
+            average
+        );
+    }
+
+    #[test]
+    fn it_should_keep_the_longest_of_think_time_and_interval() {
+        let think_time = ThinkTime::Fixed {
+            duration: Duration::from_millis(100),
+        };
+        let mut pacing = Pacing::new(
+            Some(think_time),
+            Some(Duration::from_secs(10)),
+            StdRng::seed_from_u64(0),
+        );
+
+        let delay = pacing.delay(Instant::now());
+        assert!(delay > Duration::from_secs(9), "{:?}", delay);
+
+        let long_ago = Instant::now() - Duration::from_secs(20);
+        assert_eq!(Duration::from_millis(100), pacing.delay(long_ago));
+    }
+}
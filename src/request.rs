@@ -1,40 +1,115 @@
-use std::sync::OnceLock;
+use std::sync::{Arc, OnceLock};
 use std::{fmt, fmt::Debug};
 
 use crate::batch_executor::Executable;
-use crate::config::RequestParameters;
+use crate::config::{Extraction, RequestParameters, RetryParameters, SigningParameters};
+use crate::metrics::{MetricsRecorder, RequestMetric};
+use crate::task::TaskContext;
+use crate::template::Variables;
+use crate::trace_context::{TraceContext, TraceIds};
+use crate::warmup::WarmupPhase;
+use crate::{extract, retry, signing};
 use async_trait::async_trait;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use reqwest::header::AUTHORIZATION;
 use reqwest::{RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 static DEFAULT_USER_CLIENT: OnceLock<String> = OnceLock::new();
 
 pub struct Request {
     _request_builder: RequestBuilder,
+    name: String,
+    url: String,
     executor: usize,
     task_in_executor: usize,
     request_number: usize,
     data: Option<Value>,
     _status_code: Option<u16>,
+    signing: Option<SigningParameters>,
+    retry: Option<RetryParameters>,
+    extract: Vec<Extraction>,
+    /// Set when the request must fail without being sent
+    failure: Option<RequestError>,
+    /// Seed of the retry backoff jitter
+    seed: u64,
+    warmup: Option<Arc<WarmupPhase>>,
+    trace_context: Option<Arc<TraceContext>>,
+    metrics: MetricsRecorder,
 }
 
+/// Successful response with the variables extracted from it
 #[derive(Debug)]
+pub struct RequestOutcome {
+    pub status: StatusCode,
+    pub variables: Variables,
+    /// An `extract` expression found no value, the request still succeeded
+    pub extraction_error: Option<RequestError>,
+}
+
+/// Kind of failure, reported separately in the metrics
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorClass {
+    Timeout,
+    Connect,
+    Status,
+    Request,
+    /// An `extract` expression found no value in the response
+    Extraction,
+    /// A `{{ }}` placeholder could not be rendered
+    Template,
+    /// Not sent because a previous scenario step failed
+    Skipped,
+}
+
+impl ErrorClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorClass::Timeout => "timeout",
+            ErrorClass::Connect => "connect",
+            ErrorClass::Status => "status",
+            ErrorClass::Request => "request",
+            ErrorClass::Extraction => "extraction",
+            ErrorClass::Template => "template",
+            ErrorClass::Skipped => "skipped",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct RequestError {
     pub msg: String,
+    pub class: ErrorClass,
+}
+
+impl RequestError {
+    pub fn new(class: ErrorClass, msg: String) -> Self {
+        RequestError { msg, class }
+    }
 }
 
 impl From<reqwest::Error> for RequestError {
     fn from(value: reqwest::Error) -> Self {
+        let class = if value.is_timeout() {
+            ErrorClass::Timeout
+        } else if value.is_connect() {
+            ErrorClass::Connect
+        } else {
+            ErrorClass::Request
+        };
         RequestError {
             msg: value.to_string(),
+            class,
         }
     }
 }
 impl fmt::Display for RequestError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         // Use `self.number` to refer to each positional data point.
-        write!(f, "RequestError ({})", self.msg)
+        write!(f, "RequestError [{}] ({})", self.class.as_str(), self.msg)
     }
 }
 
@@ -51,78 +126,272 @@ impl Debug for Request {
 
 #[async_trait]
 impl Executable for Request {
-    type Result = Result<StatusCode, RequestError>;
+    type Result = Result<RequestOutcome, RequestError>;
 
+    async fn execute(self) -> Self::Result {
+        let warmup = self.warmup.as_ref().is_some_and(|phase| phase.is_warmup());
+        self.run(warmup).await
+    }
+}
+impl Request {
     #[tracing::instrument(err, ret,
-        skip(self),
+        skip(self, warmup),
         fields(
-            metric_executor_id=self.executor,
-            metric_task_in_executor=self.task_in_executor,
-            metric_request_number=self.request_number
+            request_name=self.name.as_str(),
+            executor_id=self.executor,
+            task_in_executor=self.task_in_executor,
+            request_number=self.request_number,
+            warmup=warmup,
+            status_code=tracing::field::Empty,
+            error_class=tracing::field::Empty,
+            attempts=tracing::field::Empty,
+            extraction_error=tracing::field::Empty,
+            trace_id=tracing::field::Empty
         )
     )]
-    async fn execute(self) -> Self::Result {
-        // info!("starting request");
-        let resp = self._request_builder.send().await;
-        // info!("ending request");
+    async fn run(mut self, warmup: bool) -> Result<RequestOutcome, RequestError> {
+        let mut metric = RequestMetric::new(&self.name, self.executor, warmup);
+        let metrics = self.metrics.clone();
+        metrics.record(&metric);
+        let span = tracing::Span::current();
+        let trace_ids = self.trace_context.as_ref().map(|context| context.ids());
+        if let Some(trace_ids) = &trace_ids {
+            let trace_id = trace_ids.trace_id();
+            span.record("trace_id", trace_id.as_str());
+            // with the completion metric, to find the server traces
+            metric.set_trace_id(trace_id);
+        }
+        let url = std::mem::take(&mut self.url);
+        let result = self.send(trace_ids, &mut metric).await;
+        metric.complete(&result);
+        if result.is_err() {
+            metric.set_url(url);
+        }
+        metrics.record(&metric);
+        // the span only carries them to the logs
+        if let Some(status_code) = metric.status_code() {
+            span.record("status_code", status_code);
+        }
+        if let Some(error_class) = metric.error_class() {
+            span.record("error_class", error_class);
+        }
+        span.record("attempts", metric.attempts());
+        if let Ok(RequestOutcome {
+            extraction_error: Some(e),
+            ..
+        }) = &result
+        {
+            span.record("extraction_error", e.msg.as_str());
+        }
+        result
+    }
+
+    async fn send(
+        self,
+        trace_ids: Option<TraceIds>,
+        metric: &mut RequestMetric,
+    ) -> Result<RequestOutcome, RequestError> {
+        if let Some(failure) = self.failure {
+            return Err(failure);
+        }
+        let (client, request) = self._request_builder.build_split();
+        let mut request = request?;
+        if let (Some(context), Some(trace_ids)) = (&self.trace_context, &trace_ids) {
+            context.add_headers(request.headers_mut(), trace_ids, self.request_number);
+        }
+        // backoff jitter
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut attempt = 1;
+        loop {
+            // every attempt is signed again, signatures include a timestamp
+            let mut attempt_request = request.try_clone().ok_or_else(|| {
+                RequestError::new(ErrorClass::Request, "request body not clonable".to_owned())
+            })?;
+            if let Some(signing) = &self.signing {
+                signing::sign(&mut attempt_request, signing, chrono::Utc::now())?;
+            }
+            // info!("starting request");
+            let resp = client.execute(attempt_request).await;
+            // info!("ending request");
+            let status = resp.as_ref().ok().map(|resp| resp.status());
+            let retry_after = resp
+                .as_ref()
+                .ok()
+                .and_then(|resp| retry::retry_after(resp.status(), resp.headers()));
+            let result = Self::check_response(self._status_code, resp);
+
+            let retryable = self
+                .retry
+                .as_ref()
+                .is_some_and(|retry| retry.should_retry(status, result.as_ref().err()));
+            if attempt == 1 {
+                match &result {
+                    Err(e) => metric.set_first_attempt_error(e.class),
+                    Ok(_) if retryable => metric.set_first_attempt_error(ErrorClass::Status),
+                    Ok(_) => {}
+                }
+            }
+            match &self.retry {
+                Some(retry) if retryable && attempt < retry.max_attempts => {
+                    tokio::time::sleep(retry.delay(attempt, retry_after, &mut rng)).await;
+                    attempt += 1;
+                }
+                _ => {
+                    metric.set_attempts(attempt);
+                    if let (Some(status), Err(_)) = (status, &result) {
+                        metric.set_status_code(status.as_u16());
+                    }
+                    let resp = result?;
+                    let status = resp.status();
+                    // a missing value doesn't make the response a failure
+                    let extracted = extract::extract(resp, &self.extract).await;
+                    let (variables, extraction_error) = match extracted {
+                        Ok(variables) => (variables, None),
+                        Err(e) if e.class == ErrorClass::Extraction => (Variables::new(), Some(e)),
+                        Err(e) => return Err(e),
+                    };
+                    return Ok(RequestOutcome {
+                        status,
+                        variables,
+                        extraction_error,
+                    });
+                }
+            }
+        }
+    }
+
+    fn check_response(
+        expected_status: Option<u16>,
+        resp: Result<reqwest::Response, reqwest::Error>,
+    ) -> Result<reqwest::Response, RequestError> {
         match resp {
             Ok(resp) => {
-                if let Some(expected_status) = self._status_code {
+                if let Some(expected_status) = expected_status {
                     if resp.status().as_u16() != expected_status {
                         let msg = format!(
                             "status code error: expected {}, actual {}",
                             expected_status,
                             resp.status()
                         );
-                        Err(RequestError { msg })
+                        Err(RequestError::new(ErrorClass::Status, msg))
                     } else {
-                        Ok(resp.status())
+                        Ok(resp)
                     }
                 } else {
-                    Ok(resp.status())
+                    Ok(resp)
                 }
             }
             Err(e) => Err(e.into()),
         }
     }
-}
-impl Request {
+
+    /// Fails when the action isn't one of [`crate::config::ACTIONS`]
     pub fn new(
         req: RequestParameters,
-        auth: &str,
+        context: &TaskContext,
         executor: usize,
-        tasks_per_executor: usize,
         task_in_executor: usize,
-        client: &reqwest::Client
-    ) -> Self {
+        request_number: usize,
+        client: &reqwest::Client,
+        seed: u64,
+    ) -> Result<Self, RequestError> {
         DEFAULT_USER_CLIENT.get_or_init(|| {
             let default_user_agent =
                 format!("{}_v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
             default_user_agent
         });
-        let _auth = "Bearer ".to_owned() + &auth;
+        let _auth = "Bearer ".to_owned() + &context.auth;
+        let name = req.display_name();
         // let client = reqwest::Client::new();
-        let request_number = (executor * tasks_per_executor) + task_in_executor;
 
         let request_builder = match req.action.as_str() {
             "POST" => client.post(&req.url),
             "PUT" => client.put(&req.url),
             "GET" => client.get(&req.url),
-            _ => panic!("action not supported"),
+            "PATCH" => client.patch(&req.url),
+            "DELETE" => client.delete(&req.url),
+            action => {
+                return Err(RequestError::new(
+                    ErrorClass::Request,
+                    format!("action not supported: {}", action),
+                ))
+            }
         };
 
+        let mut request_builder = request_builder.header(AUTHORIZATION, _auth.clone());
+        for (name, value) in &req.headers {
+            request_builder = request_builder.header(name, value);
+        }
+        if let Some(request_timeout) = req.request_timeout {
+            request_builder = request_builder.timeout(request_timeout);
+        }
         let mut data: Option<Value> = None;
         if let Some(orig_data) = req.data {
+            // sent as the json body, set before sending since signatures
+            // are computed over it
+            request_builder = request_builder.json(&orig_data);
             data = Some(orig_data);
         }
-        Self {
-            _request_builder: request_builder.header(AUTHORIZATION, _auth.clone()),
+        Ok(Self {
+            _request_builder: request_builder,
+            name,
+            url: req.url,
             executor,
             task_in_executor,
             request_number,
             data,
             _status_code: req.status_code,
+            signing: req.signing,
+            retry: req.retry,
+            extract: req.extract,
+            failure: None,
+            seed,
+            warmup: context.warmup.clone(),
+            trace_context: context.trace_context.clone(),
+            metrics: context.metrics.clone(),
+        })
+    }
+
+    /// Request that can't be built, failing with `error` without being
+    /// sent so it is still reported in the metrics
+    pub fn failed(
+        name: String,
+        error: RequestError,
+        context: &TaskContext,
+        executor: usize,
+        task_in_executor: usize,
+        request_number: usize,
+        client: &reqwest::Client,
+    ) -> Self {
+        Self {
+            _request_builder: client.get(""),
+            name,
+            url: String::new(),
+            executor,
+            task_in_executor,
+            request_number,
+            data: None,
+            _status_code: None,
+            signing: None,
+            retry: None,
+            extract: vec![],
+            failure: Some(error),
+            seed: 0,
+            warmup: context.warmup.clone(),
+            trace_context: context.trace_context.clone(),
+            metrics: context.metrics.clone(),
         }
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Makes the request fail with `error` instead of being sent, so it is
+    /// still reported in the metrics
+    pub fn with_failure(mut self, error: RequestError) -> Self {
+        self.failure = Some(error);
+        self
+    }
+
 }
@@ -0,0 +1,217 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Lines};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+
+use crate::config::{Configuration, RequestParameters};
+use crate::request::{ErrorClass, RequestError};
+
+/// JSONL file with one request per line, read line by line so large files
+/// are never fully loaded in memory
+#[derive(Debug)]
+pub(crate) struct RequestsFile {
+    path: Arc<PathBuf>,
+    len: usize,
+    connect_timeouts: BTreeSet<Duration>,
+}
+
+impl RequestsFile {
+    /// Reads the whole file once, to check every line and count the requests
+    pub(crate) fn open(path: &Path, conf: &Configuration) -> anyhow::Result<Self> {
+        let mut len = 0;
+        let mut connect_timeouts = BTreeSet::new();
+        let file = File::open(path).with_context(|| format!("error opening {:?}", path))?;
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.with_context(|| format!("error reading {:?}", path))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let req = serde_json::from_str::<RequestParameters>(&line)
+                .map_err(anyhow::Error::from)
+                .and_then(|req| conf.validate_request(&req).map(|_| req))
+                .with_context(|| format!("invalid request at line {} of {:?}", number + 1, path))?;
+            connect_timeouts.extend(req.connect_timeout);
+            len += 1;
+        }
+        Ok(Self {
+            path: Arc::new(path.to_owned()),
+            len,
+            connect_timeouts,
+        })
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Distinct connect timeouts set by the requests, each needs its own client
+    pub(crate) fn connect_timeouts(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.connect_timeouts.iter().copied()
+    }
+
+    pub(crate) fn iter(&self) -> RequestsFileIter {
+        RequestsFileIter {
+            path: self.path.clone(),
+            lines: None,
+            position: 0,
+            failed: false,
+        }
+    }
+}
+
+/// The file is opened on the first call to `next`
+pub(crate) struct RequestsFileIter {
+    path: Arc<PathBuf>,
+    lines: Option<Lines<BufReader<File>>>,
+    /// Lines already read, empty ones included
+    position: usize,
+    /// Set once the file couldn't be read, the lines after aren't trusted
+    failed: bool,
+}
+
+impl Clone for RequestsFileIter {
+    /// A file handle can't be shared: the clone opens the file again and
+    /// skips the lines already read
+    fn clone(&self) -> Self {
+        Self {
+            path: self.path.clone(),
+            lines: None,
+            position: self.position,
+            failed: self.failed,
+        }
+    }
+}
+
+impl Iterator for RequestsFileIter {
+    type Item = Result<RequestParameters, RequestError>;
+
+    /// Lines were checked when opening the file, so failing now means it
+    /// changed during the run: the error is returned once, as a failed
+    /// request, and the iteration stops
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let next = self.next_line();
+        if let Some(Err(_)) = next {
+            self.failed = true;
+        }
+        next
+    }
+}
+
+impl RequestsFileIter {
+    fn next_line(&mut self) -> Option<Result<RequestParameters, RequestError>> {
+        let changed = |msg: String| {
+            RequestError::new(
+                ErrorClass::Request,
+                format!("requests file changed during the run: {}", msg),
+            )
+        };
+        if self.lines.is_none() {
+            let file = match File::open(&*self.path) {
+                Ok(file) => file,
+                Err(e) => {
+                    return Some(Err(changed(format!(
+                        "error opening {:?}: {}",
+                        self.path, e
+                    ))))
+                }
+            };
+            let mut lines = BufReader::new(file).lines();
+            lines.by_ref().take(self.position).for_each(drop);
+            self.lines = Some(lines);
+        }
+        let lines = self.lines.as_mut().expect("opened above");
+        for line in lines {
+            self.position += 1;
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    return Some(Err(changed(format!(
+                        "error reading {:?}: {}",
+                        self.path, e
+                    ))))
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            return Some(serde_json::from_str(&line).map_err(|e| {
+                changed(format!(
+                    "invalid request at line {} of {:?}: {}",
+                    self.position, self.path, e
+                ))
+            }));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::RequestsFileIter;
+
+    #[test]
+    fn it_should_stream_requests_and_clone_at_the_same_line() {
+        let path =
+            std::env::temp_dir().join(format!("parreq-requests-{}.jsonl", std::process::id()));
+        let mut file = std::fs::File::create(&path).unwrap();
+        writeln!(file, r#"{{"action": "GET", "url": "http://localhost/1"}}"#).unwrap();
+        writeln!(file).unwrap();
+        writeln!(file, r#"{{"action": "GET", "url": "http://localhost/2"}}"#).unwrap();
+        writeln!(
+            file,
+            r#"{{"action": "POST", "url": "http://localhost/3", "data": {{}}}}"#
+        )
+        .unwrap();
+        let mut requests = RequestsFileIter {
+            path: std::sync::Arc::new(path.clone()),
+            lines: None,
+            position: 0,
+            failed: false,
+        };
+
+        assert_eq!("http://localhost/1", requests.next().unwrap().unwrap().url);
+        let clone = requests.clone();
+        let urls: Vec<_> = requests.map(|req| req.unwrap().url).collect();
+        let cloned_urls: Vec<_> = clone.map(|req| req.unwrap().url).collect();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(vec!["http://localhost/2", "http://localhost/3"], urls);
+        assert_eq!(urls, cloned_urls);
+    }
+
+    #[test]
+    fn it_should_fail_once_when_the_file_changed() {
+        let path = std::env::temp_dir().join(format!(
+            "parreq-requests-changed-{}.jsonl",
+            std::process::id()
+        ));
+        std::fs::write(
+            &path,
+            "{\"action\": \"GET\", \"url\": \"http://localhost/1\"}\nnot json\n{}\n",
+        )
+        .unwrap();
+        let requests = RequestsFileIter {
+            path: std::sync::Arc::new(path.clone()),
+            lines: None,
+            position: 0,
+            failed: false,
+        };
+
+        let results: Vec<_> = requests.collect();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(2, results.len());
+        assert!(results[0].is_ok());
+        let error = results[1].as_ref().unwrap_err();
+        assert!(error.msg.contains("line 2"), "{}", error.msg);
+    }
+}
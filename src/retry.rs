@@ -0,0 +1,179 @@
+use std::time::Duration;
+
+use anyhow::bail;
+use rand::Rng;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+
+use crate::config::RetryParameters;
+use crate::request::RequestError;
+
+impl RetryParameters {
+    /// Checks the values the backoff can't be computed from
+    pub(crate) fn validate(&self) -> anyhow::Result<()> {
+        if self.max_attempts == 0 {
+            bail!("retry: max_attempts must be at least 1");
+        }
+        if !self.multiplier.is_finite() || self.multiplier < 1.0 {
+            bail!(
+                "retry: multiplier must be a number of at least 1, not {}",
+                self.multiplier
+            );
+        }
+        if self.initial_backoff > self.max_backoff {
+            bail!(
+                "retry: initial_backoff {:?} is greater than max_backoff {:?}",
+                self.initial_backoff,
+                self.max_backoff
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether an attempt with this outcome should be retried (attempts left aside)
+    pub(crate) fn should_retry(
+        &self,
+        status: Option<StatusCode>,
+        error: Option<&RequestError>,
+    ) -> bool {
+        status.is_some_and(|status| self.status_codes.contains(&status.as_u16()))
+            || error.is_some_and(|error| self.error_classes.contains(&error.class))
+    }
+
+    /// Time to wait after the failed `attempt` (starting at 1) before the next one
+    pub(crate) fn backoff(&self, attempt: usize, rng: &mut impl Rng) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as usize) as i32;
+        let backoff = self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent);
+        let backoff = Duration::from_secs_f64(backoff.min(self.max_backoff.as_secs_f64()));
+        if self.jitter && !backoff.is_zero() {
+            rng.gen_range(Duration::ZERO..=backoff)
+        } else {
+            backoff
+        }
+    }
+
+    /// Time to wait before the next attempt, as requested by the server in
+    /// `retry_after` when respected, never longer than `max_backoff`
+    pub(crate) fn delay(
+        &self,
+        attempt: usize,
+        retry_after: Option<Duration>,
+        rng: &mut impl Rng,
+    ) -> Duration {
+        match retry_after.filter(|_| self.respect_retry_after) {
+            Some(retry_after) => retry_after.min(self.max_backoff),
+            None => self.backoff(attempt, rng),
+        }
+    }
+}
+
+/// Delay requested by the server in the `Retry-After` header of 429 and 503
+/// responses, either in seconds or as an http date.
+pub(crate) fn retry_after(status: StatusCode, headers: &HeaderMap) -> Option<Duration> {
+    if status != StatusCode::TOO_MANY_REQUESTS && status != StatusCode::SERVICE_UNAVAILABLE {
+        return None;
+    }
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let delay = date.timestamp_millis() - chrono::Utc::now().timestamp_millis();
+    Some(Duration::from_millis(delay.max(0) as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rand::rngs::mock::StepRng;
+    use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+    use reqwest::StatusCode;
+
+    use super::retry_after;
+    use crate::config::RetryParameters;
+
+    fn retry_parameters(jitter: bool) -> RetryParameters {
+        serde_json::from_value(serde_json::json!({
+            "initial_backoff": "100ms",
+            "max_backoff": "1s",
+            "jitter": jitter
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn it_should_reject_a_backoff_that_can_not_be_computed() {
+        let mut retry = retry_parameters(false);
+        assert!(retry.validate().is_ok());
+
+        retry.multiplier = -2.0;
+        assert!(retry.validate().is_err());
+        retry.multiplier = f64::NAN;
+        assert!(retry.validate().is_err());
+        retry.multiplier = 2.0;
+        retry.max_attempts = 0;
+        assert!(retry.validate().is_err());
+        retry.max_attempts = 3;
+        retry.initial_backoff = Duration::from_secs(2);
+        assert!(retry.validate().is_err());
+    }
+
+    #[test]
+    fn it_should_grow_exponentially_up_to_max_backoff() {
+        let retry = retry_parameters(false);
+        let mut rng = StepRng::new(0, 1);
+
+        assert_eq!(Duration::from_millis(100), retry.backoff(1, &mut rng));
+        assert_eq!(Duration::from_millis(200), retry.backoff(2, &mut rng));
+        assert_eq!(Duration::from_millis(400), retry.backoff(3, &mut rng));
+        assert_eq!(Duration::from_secs(1), retry.backoff(10, &mut rng));
+    }
+
+    #[test]
+    fn it_should_keep_jitter_below_backoff() {
+        let retry = retry_parameters(true);
+        let mut rng = rand::thread_rng();
+
+        for attempt in 1..10 {
+            assert!(retry.backoff(attempt, &mut rng) <= Duration::from_secs(1));
+        }
+    }
+
+    #[test]
+    fn it_should_cap_retry_after_at_max_backoff() {
+        let retry = retry_parameters(false);
+        let mut rng = StepRng::new(0, 1);
+
+        assert_eq!(
+            Duration::from_millis(300),
+            retry.delay(1, Some(Duration::from_millis(300)), &mut rng)
+        );
+        assert_eq!(
+            Duration::from_secs(1),
+            retry.delay(1, Some(Duration::from_secs(3600)), &mut rng)
+        );
+        assert_eq!(Duration::from_millis(100), retry.delay(1, None, &mut rng));
+    }
+
+    #[test]
+    fn it_should_honor_retry_after_only_on_429_and_503() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("3"));
+
+        assert_eq!(
+            Some(Duration::from_secs(3)),
+            retry_after(StatusCode::TOO_MANY_REQUESTS, &headers)
+        );
+        assert_eq!(None, retry_after(StatusCode::BAD_GATEWAY, &headers));
+
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(
+            Some(Duration::ZERO),
+            retry_after(StatusCode::SERVICE_UNAVAILABLE, &headers)
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use std::ops::ControlFlow;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::batch_executor::Executable;
+use crate::config::ScenarioParameters;
+use crate::feeder::Feeder;
+use crate::request::{ErrorClass, Request, RequestError};
+use crate::task::TaskContext;
+use crate::template;
+
+/// One run of a scenario by a virtual user: the steps are sent in order, each
+/// one rendered with the variables extracted by the previous ones.
+pub(crate) struct ScenarioRun {
+    /// Feeds every run of the scenario
+    feeder: Option<Arc<Feeder>>,
+    /// Feeds each step
+    step_feeders: Vec<Option<Arc<Feeder>>>,
+    scenario: Arc<ScenarioParameters>,
+    /// Client for each step, as they may have different connect timeouts
+    clients: Vec<reqwest::Client>,
+    context: Arc<TaskContext>,
+    executor: usize,
+    task_in_executor: usize,
+    request_number: usize,
+    seed: u64,
+}
+
+impl ScenarioRun {
+    pub(crate) fn new(
+        scenario: Arc<ScenarioParameters>,
+        context: &Arc<TaskContext>,
+        executor: usize,
+        task_in_executor: usize,
+        request_number: usize,
+        seed: u64,
+    ) -> Self {
+        let feeder =
+            |name: &Option<String>| name.as_ref().map(|name| context.feeders[name].clone());
+        Self {
+            feeder: feeder(&scenario.feeder),
+            step_feeders: scenario
+                .steps
+                .iter()
+                .map(|step| feeder(&step.feeder))
+                .collect(),
+            clients: scenario
+                .steps
+                .iter()
+                .map(|step| {
+                    context
+                        .clients
+                        .for_connect_timeout(step.connect_timeout)
+                        .clone()
+                })
+                .collect(),
+            scenario,
+            context: context.clone(),
+            executor,
+            task_in_executor,
+            request_number,
+            seed,
+        }
+    }
+
+    fn request(
+        &self,
+        step: crate::config::RequestParameters,
+        client: &reqwest::Client,
+        seed: u64,
+    ) -> Request {
+        let name = step.display_name();
+        Request::new(
+            step,
+            &self.context,
+            self.executor,
+            self.task_in_executor,
+            self.request_number,
+            client,
+            seed,
+        )
+        .unwrap_or_else(|error| {
+            Request::failed(
+                name,
+                error,
+                &self.context,
+                self.executor,
+                self.task_in_executor,
+                self.request_number,
+                client,
+            )
+        })
+    }
+}
+
+#[async_trait]
+impl Executable for ScenarioRun {
+    /// Breaks when a feeder is exhausted, the remaining steps not being sent
+    type Result = ControlFlow<()>;
+
+    async fn execute(self) -> Self::Result {
+        let mut variables = template::builtin_variables(self.executor, self.request_number);
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        if let Some(feeder) = &self.feeder {
+            if !feeder.feed(self.executor, &mut variables, &mut rng) {
+                return ControlFlow::Break(());
+            }
+        }
+        let mut failed_step: Option<String> = None;
+        let steps = self
+            .scenario
+            .steps
+            .iter()
+            .zip(&self.clients)
+            .zip(&self.step_feeders);
+        for ((step, client), step_feeder) in steps {
+            let mut step = step.clone();
+            // every step is reported on its own in the metrics
+            step.name = Some(format!("{} / {}", self.scenario.name, step.display_name()));
+            let request = if let Some(failed_step) = &failed_step {
+                let msg = format!("previous step failed: {}", failed_step);
+                self.request(step, client, rng.gen())
+                    .with_failure(RequestError::new(ErrorClass::Skipped, msg))
+            } else {
+                if let Some(feeder) = step_feeder {
+                    if !feeder.feed(self.executor, &mut variables, &mut rng) {
+                        return ControlFlow::Break(());
+                    }
+                }
+                match template::render_request(&step, &variables, &mut rng) {
+                    Ok(rendered) => self.request(rendered, client, rng.gen()),
+                    Err(msg) => self
+                        .request(step, client, rng.gen())
+                        .with_failure(RequestError::new(ErrorClass::Template, msg)),
+                }
+            };
+            let name = request.name().to_owned();
+            match request.execute().await {
+                // the next steps would miss the extracted variables
+                Ok(outcome) if outcome.extraction_error.is_some() => {
+                    failed_step = failed_step.or(Some(name))
+                }
+                Ok(outcome) => variables.extend(outcome.variables),
+                Err(_) => failed_step = failed_step.or(Some(name)),
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
@@ -0,0 +1,241 @@
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::header::{HeaderName, HeaderValue, HOST};
+use sha2::{Digest, Sha256};
+
+use crate::config::{AwsSigningParameters, HmacSigningParameters, SigningParameters};
+use crate::request::{ErrorClass, RequestError};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const AWS_DATE_HEADER: &str = "x-amz-date";
+const AWS_SECURITY_TOKEN_HEADER: &str = "x-amz-security-token";
+
+/// Signs an already built request. It has to be done right before sending it,
+/// since every signature includes the current timestamp.
+pub(crate) fn sign(
+    request: &mut reqwest::Request,
+    params: &SigningParameters,
+    now: DateTime<Utc>,
+) -> Result<(), RequestError> {
+    match params {
+        SigningParameters::Hmac(params) => sign_hmac(request, params, now),
+        SigningParameters::AwsSigv4(params) => sign_aws_sigv4(request, params, now),
+    }
+}
+
+fn sign_hmac(
+    request: &mut reqwest::Request,
+    params: &HmacSigningParameters,
+    now: DateTime<Utc>,
+) -> Result<(), RequestError> {
+    let timestamp = now.timestamp().to_string();
+    let mut path = request.url().path().to_owned();
+    if let Some(query) = request.url().query() {
+        path.push('?');
+        path.push_str(query);
+    }
+    let mut string_to_sign =
+        format!("{}\n{}\n{}\n", request.method(), path, timestamp).into_bytes();
+    string_to_sign.extend_from_slice(body_bytes(request));
+
+    let signature = hex::encode(hmac_sha256(params.secret.as_bytes(), &string_to_sign));
+    insert_header(request, &params.timestamp_header, &timestamp)?;
+    insert_header(request, &params.signature_header, &signature)
+}
+
+fn sign_aws_sigv4(
+    request: &mut reqwest::Request,
+    params: &AwsSigningParameters,
+    now: DateTime<Utc>,
+) -> Result<(), RequestError> {
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let host = match (request.url().host_str(), request.url().port()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_owned(),
+        (None, _) => {
+            return Err(RequestError::new(
+                ErrorClass::Request,
+                format!("signing error: url without host {}", request.url()),
+            ))
+        }
+    };
+
+    let mut headers = vec![("host", host.clone()), (AWS_DATE_HEADER, amz_date.clone())];
+    if let Some(token) = &params.session_token {
+        headers.push((AWS_SECURITY_TOKEN_HEADER, token.clone()));
+    }
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        request.method(),
+        uri_encode(request.url().path(), false),
+        canonical_query(request.url()),
+        canonical_headers,
+        signed_headers,
+        hex::encode(Sha256::digest(body_bytes(request)))
+    );
+    let scope = format!("{}/{}/{}/aws4_request", date, params.region, params.service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let secret = format!("AWS4{}", params.secret_access_key);
+    let k_date = hmac_sha256(secret.as_bytes(), date.as_bytes());
+    let k_region = hmac_sha256(&k_date, params.region.as_bytes());
+    let k_service = hmac_sha256(&k_region, params.service.as_bytes());
+    let k_signing = hmac_sha256(&k_service, b"aws4_request");
+    let signature = hex::encode(hmac_sha256(&k_signing, string_to_sign.as_bytes()));
+
+    let authorization = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        params.access_key_id, scope, signed_headers, signature
+    );
+    insert_header(request, HOST.as_str(), &host)?;
+    insert_header(request, AWS_DATE_HEADER, &amz_date)?;
+    if let Some(token) = &params.session_token {
+        insert_header(request, AWS_SECURITY_TOKEN_HEADER, token)?;
+    }
+    // replaces the bearer token set when building the request
+    insert_header(request, "authorization", &authorization)
+}
+
+fn body_bytes(request: &reqwest::Request) -> &[u8] {
+    request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .unwrap_or_default()
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("hmac accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn canonical_query(url: &reqwest::Url) -> String {
+    let mut pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(k, v)| (uri_encode(&k, true), uri_encode(&v, true)))
+        .collect();
+    pairs.sort();
+    pairs
+        .into_iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// URI encoding as specified by AWS: every byte except the unreserved
+/// characters is percent encoded, and '/' only when `encode_slash` is set.
+fn uri_encode(input: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(input.len());
+    for byte in input.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn insert_header(
+    request: &mut reqwest::Request,
+    name: &str,
+    value: &str,
+) -> Result<(), RequestError> {
+    let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| {
+        RequestError::new(
+            ErrorClass::Request,
+            format!("signing error: invalid header name {}: {}", name, e),
+        )
+    })?;
+    let value = HeaderValue::from_str(value).map_err(|e| {
+        RequestError::new(
+            ErrorClass::Request,
+            format!("signing error: invalid header value: {}", e),
+        )
+    })?;
+    request.headers_mut().insert(name, value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use reqwest::{Method, Request, Url};
+
+    use super::sign;
+    use crate::config::{AwsSigningParameters, HmacSigningParameters, SigningParameters};
+
+    #[test]
+    fn it_should_sign_aws_get_vanilla() {
+        // "get-vanilla" case from the AWS SigV4 test suite
+        let mut request = Request::new(
+            Method::GET,
+            Url::parse("https://example.amazonaws.com/").unwrap(),
+        );
+        let params = SigningParameters::AwsSigv4(AwsSigningParameters {
+            access_key_id: "AKIDEXAMPLE".to_owned(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_owned(),
+            session_token: None,
+            region: "us-east-1".to_owned(),
+            service: "service".to_owned(),
+        });
+        let now = Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap();
+
+        sign(&mut request, &params, now).unwrap();
+
+        assert_eq!(
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31",
+            request.headers()["authorization"]
+        );
+        assert_eq!("20150830T123600Z", request.headers()["x-amz-date"]);
+    }
+
+    #[test]
+    fn it_should_sign_hmac_with_timestamp() {
+        let mut request = Request::new(
+            Method::POST,
+            Url::parse("https://example.com/orders?page=1").unwrap(),
+        );
+        *request.body_mut() = Some("{}".into());
+        let params = SigningParameters::Hmac(HmacSigningParameters {
+            secret: "secret".to_owned(),
+            signature_header: "X-Signature".to_owned(),
+            timestamp_header: "X-Timestamp".to_owned(),
+        });
+        let now = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+
+        sign(&mut request, &params, now).unwrap();
+
+        assert_eq!("1700000000", request.headers()["x-timestamp"]);
+        let signature = request.headers()["x-signature"].to_str().unwrap();
+        assert_eq!(64, signature.len());
+        assert_eq!(
+            hex::encode(super::hmac_sha256(
+                b"secret",
+                b"POST\n/orders?page=1\n1700000000\n{}"
+            )),
+            signature
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use std::ops::ControlFlow;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::batch_executor::Executable;
+use crate::client::HttpClients;
+use crate::config::{RequestParameters, ScenarioParameters};
+use crate::feeder::{Feeder, Feeders};
+use crate::request::{ErrorClass, Request, RequestError};
+use crate::scenario::ScenarioRun;
+use crate::metrics::MetricsRecorder;
+use crate::template;
+use crate::trace_context::TraceContext;
+use crate::warmup::WarmupPhase;
+
+/// What an executor has to run next, before creating the actual task
+#[derive(Debug, Clone)]
+pub(crate) enum TaskParameters {
+    Request(Box<RequestParameters>),
+    Scenario(Arc<ScenarioParameters>),
+    /// A request that couldn't be read, reported as failed without being sent
+    Failed(Box<RequestError>),
+}
+
+impl TaskParameters {
+    /// Number of requests sent by the task
+    pub(crate) fn requests(&self) -> usize {
+        match self {
+            TaskParameters::Request(_) | TaskParameters::Failed(_) => 1,
+            TaskParameters::Scenario(scenario) => scenario.steps.len(),
+        }
+    }
+}
+
+/// What all the tasks share
+pub(crate) struct TaskContext {
+    pub auth: Arc<str>,
+    pub clients: HttpClients,
+    pub feeders: Feeders,
+    pub warmup: Option<Arc<WarmupPhase>>,
+    pub trace_context: Option<Arc<TraceContext>>,
+    pub metrics: MetricsRecorder,
+    /// Seed of the run, each task derives its own from it
+    pub seed: u64,
+}
+
+pub(crate) enum Task {
+    /// Request built in advance, it has no placeholders
+    Request(Box<Request>),
+    /// Request rendered and built right before being sent, so generated
+    /// values (timestamps, sequences...) are computed at send time
+    Templated(Box<TemplatedRequest>),
+    Scenario(ScenarioRun),
+}
+
+pub(crate) struct TemplatedRequest {
+    params: RequestParameters,
+    feeder: Option<Arc<Feeder>>,
+    client: reqwest::Client,
+    context: Arc<TaskContext>,
+    executor: usize,
+    task_in_executor: usize,
+    request_number: usize,
+    seed: u64,
+}
+
+impl Task {
+    pub(crate) fn new(
+        params: TaskParameters,
+        context: &Arc<TaskContext>,
+        executor: usize,
+        task_in_executor: usize,
+        request_number: usize,
+    ) -> Self {
+        let seed = task_seed(context.seed, request_number);
+        let clients = &context.clients;
+        match params {
+            TaskParameters::Request(req)
+                if template::has_placeholders(&req) || req.feeder.is_some() =>
+            {
+                let client = clients.for_connect_timeout(req.connect_timeout).clone();
+                Task::Templated(Box::new(TemplatedRequest {
+                    feeder: req.feeder.as_ref().map(|name| context.feeders[name].clone()),
+                    params: *req,
+                    client,
+                    context: context.clone(),
+                    executor,
+                    task_in_executor,
+                    request_number,
+                    seed,
+                }))
+            }
+            TaskParameters::Request(req) => {
+                let client = clients.for_connect_timeout(req.connect_timeout);
+                let name = req.display_name();
+                let request = Request::new(
+                    *req,
+                    context,
+                    executor,
+                    task_in_executor,
+                    request_number,
+                    client,
+                    seed,
+                )
+                .unwrap_or_else(|error| {
+                    Request::failed(
+                        name,
+                        error,
+                        context,
+                        executor,
+                        task_in_executor,
+                        request_number,
+                        client,
+                    )
+                });
+                Task::Request(Box::new(request))
+            }
+            TaskParameters::Failed(error) => Task::Request(Box::new(Request::failed(
+                "requests file".to_owned(),
+                *error,
+                context,
+                executor,
+                task_in_executor,
+                request_number,
+                clients.default_client(),
+            ))),
+            TaskParameters::Scenario(scenario) => Task::Scenario(ScenarioRun::new(
+                scenario,
+                context,
+                executor,
+                task_in_executor,
+                request_number,
+                seed,
+            )),
+        }
+    }
+}
+
+/// Seed of the random values of a task. It only depends on the seed of the
+/// run and the number of the task, not on the executor running it.
+pub(crate) fn task_seed(seed: u64, request_number: usize) -> u64 {
+    seed ^ (request_number as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+}
+
+impl TemplatedRequest {
+    /// `None` when its feeder is exhausted and configured to stop
+    fn render(self) -> Option<Request> {
+        let mut variables = template::builtin_variables(self.executor, self.request_number);
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        // named after the template, not after every rendered url
+        let mut params = self.params;
+        params.name = Some(params.display_name());
+        if let Some(feeder) = &self.feeder {
+            if !feeder.feed(self.executor, &mut variables, &mut rng) {
+                return None;
+            }
+        }
+        let name = params.display_name();
+        let request = match template::render_request(&params, &variables, &mut rng) {
+            Ok(rendered) => Request::new(
+                rendered,
+                &self.context,
+                self.executor,
+                self.task_in_executor,
+                self.request_number,
+                &self.client,
+                rng.gen(),
+            ),
+            Err(msg) => Err(RequestError::new(ErrorClass::Template, msg)),
+        };
+        Some(request.unwrap_or_else(|error| {
+            Request::failed(
+                name,
+                error,
+                &self.context,
+                self.executor,
+                self.task_in_executor,
+                self.request_number,
+                &self.client,
+            )
+        }))
+    }
+}
+
+#[async_trait]
+impl Executable for Task {
+    /// Breaks when the executor has nothing left to send
+    type Result = ControlFlow<()>;
+
+    async fn execute(self) -> Self::Result {
+        // failures are already reported through the request span
+        match self {
+            Task::Request(request) => {
+                let _ = request.execute().await;
+            }
+            Task::Templated(templated) => {
+                let Some(request) = templated.render() else {
+                    return ControlFlow::Break(());
+                };
+                let _ = request.execute().await;
+            }
+            Task::Scenario(scenario) => return scenario.execute().await,
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::batcher;
+    use crate::task::task_seed;
+
+    #[test]
+    fn it_should_give_distinct_seeds_for_an_uneven_split() {
+        // 5 tasks shared by 3 executors, the first one taking 3 of them
+        let mut executors = batcher::share((0..5).enumerate(), 3);
+        let taken: Vec<Vec<usize>> = vec![
+            executors[0].by_ref().take(3).map(|(i, _)| i).collect(),
+            executors[1].by_ref().take(1).map(|(i, _)| i).collect(),
+            executors[2].by_ref().map(|(i, _)| i).collect(),
+        ];
+
+        let seeds: HashSet<u64> = taken
+            .iter()
+            .flatten()
+            .map(|task_index| task_seed(42, task_index + 1))
+            .collect();
+
+        assert_eq!(vec![vec![0, 1, 2], vec![3], vec![4]], taken);
+        assert_eq!(5, seeds.len());
+    }
+}
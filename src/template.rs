@@ -0,0 +1,363 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde_json::Value;
+
+use crate::config::RequestParameters;
+
+const OPEN: &str = "{{";
+const CLOSE: &str = "}}";
+/// Longest string generated by `random_string`
+const MAX_STRING_LENGTH: i64 = 1 << 20;
+
+/// Variables available to `{{ name }}` placeholders
+pub(crate) type Variables = HashMap<String, String>;
+
+/// Variables every template can use, besides the extracted ones
+pub(crate) fn builtin_variables(executor: usize, request_number: usize) -> Variables {
+    Variables::from([
+        ("executor_id".to_owned(), executor.to_string()),
+        ("request_number".to_owned(), request_number.to_string()),
+    ])
+}
+
+/// Counters shared by all the executors, one per `sequence("name")`
+static SEQUENCES: OnceLock<Mutex<HashMap<String, Arc<AtomicU64>>>> = OnceLock::new();
+
+/// Replaces the `{{ }}` placeholders in url, headers and data
+pub(crate) fn render_request(
+    req: &RequestParameters,
+    variables: &Variables,
+    rng: &mut impl Rng,
+) -> Result<RequestParameters, String> {
+    let mut rendered = req.clone();
+    rendered.url = render(&req.url, variables, rng)?;
+    for value in rendered.headers.values_mut() {
+        *value = render(value, variables, rng)?;
+    }
+    if let Some(data) = &req.data {
+        rendered.data = Some(render_value(data, variables, rng)?);
+    }
+    Ok(rendered)
+}
+
+/// Renders every string in a json value. A string made of a single
+/// `{{ expr | number }}` placeholder becomes a json number, the others stay
+/// strings (so ids like "00042" are sent as they are).
+pub(crate) fn render_value(
+    value: &Value,
+    variables: &Variables,
+    rng: &mut impl Rng,
+) -> Result<Value, String> {
+    Ok(match value {
+        Value::String(s) => {
+            let rendered = render(s, variables, rng)?;
+            match single_placeholder(s).and_then(number_filter) {
+                // the filter already checked it is a number
+                Some(_) => serde_json::from_str(&rendered).map_err(|e| e.to_string())?,
+                None => Value::String(rendered),
+            }
+        }
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| render_value(item, variables, rng))
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| Ok((k.clone(), render_value(v, variables, rng)?)))
+                .collect::<Result<_, String>>()?,
+        ),
+        other => other.clone(),
+    })
+}
+
+pub(crate) fn render(
+    template: &str,
+    variables: &Variables,
+    rng: &mut impl Rng,
+) -> Result<String, String> {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find(OPEN) {
+        output.push_str(&rest[..start]);
+        let expr = &rest[start + OPEN.len()..];
+        let end = expr
+            .find(CLOSE)
+            .ok_or_else(|| format!("unterminated '{}' in \"{}\"", OPEN, template))?;
+        output.push_str(&evaluate(expr[..end].trim(), variables, rng)?);
+        rest = &expr[end + CLOSE.len()..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Whether the templates has any placeholder, to skip rendering otherwise
+pub(crate) fn has_placeholders(req: &RequestParameters) -> bool {
+    req.url.contains(OPEN)
+        || req.headers.values().any(|value| value.contains(OPEN))
+        || req
+            .data
+            .as_ref()
+            .is_some_and(|data| data.to_string().contains(OPEN))
+}
+
+/// Expression of a template made of a single placeholder
+fn single_placeholder(template: &str) -> Option<&str> {
+    let expr = template.trim().strip_prefix(OPEN)?.strip_suffix(CLOSE)?;
+    (!expr.contains(OPEN)).then(|| expr.trim())
+}
+
+/// Expression of `expr | number`
+fn number_filter(expr: &str) -> Option<&str> {
+    let (expr, filter) = expr.rsplit_once('|')?;
+    (filter.trim() == "number").then(|| expr.trim())
+}
+
+/// Evaluates either a variable (`name`) or a built-in function (`name(args)`),
+/// optionally followed by `| number`
+fn evaluate(expr: &str, variables: &Variables, rng: &mut impl Rng) -> Result<String, String> {
+    if let Some(expr) = number_filter(expr) {
+        let value = evaluate(expr, variables, rng)?;
+        return match serde_json::from_str::<Value>(&value) {
+            Ok(Value::Number(_)) => Ok(value),
+            _ => Err(format!("number: '{}' is not a number", value)),
+        };
+    }
+    let Some((name, args)) = expr.split_once('(') else {
+        return variables
+            .get(expr)
+            .cloned()
+            .ok_or_else(|| format!("unknown variable '{}'", expr));
+    };
+    let args = args
+        .trim_end()
+        .strip_suffix(')')
+        .ok_or_else(|| format!("missing ')' in '{}'", expr))?;
+    let args = parse_args(args)?;
+    match (name.trim(), args.as_slice()) {
+        ("uuid", []) => Ok(uuid::Builder::from_random_bytes(rng.gen())
+            .into_uuid()
+            .to_string()),
+        ("random_int", [min, max]) => {
+            let (min, max) = (int_arg(min)?, int_arg(max)?);
+            if min > max {
+                return Err(format!("random_int: {} is greater than {}", min, max));
+            }
+            Ok(rng.gen_range(min..=max).to_string())
+        }
+        ("random_string", [len]) => Ok(random_string(rng, length_arg(len)?)),
+        ("random_string", [min, max]) => {
+            let (min, max) = (length_arg(min)?, length_arg(max)?);
+            if min > max {
+                return Err(format!("random_string: {} is greater than {}", min, max));
+            }
+            let len = rng.gen_range(min..=max);
+            Ok(random_string(rng, len))
+        }
+        ("timestamp", []) => Ok(chrono::Utc::now().timestamp().to_string()),
+        ("timestamp_ms", []) => Ok(chrono::Utc::now().timestamp_millis().to_string()),
+        ("now", []) => Ok(chrono::Utc::now().to_rfc3339()),
+        ("sequence", []) => Ok(next_in_sequence("", 1).to_string()),
+        ("sequence", [name]) => Ok(next_in_sequence(name, 1).to_string()),
+        ("sequence", [name, start]) => {
+            let start = int_arg(start)?;
+            if start < 0 {
+                return Err(format!("sequence: negative start {}", start));
+            }
+            Ok(next_in_sequence(name, start as u64).to_string())
+        }
+        ("pick", options) if !options.is_empty() => {
+            Ok(options[rng.gen_range(0..options.len())].clone())
+        }
+        (name, args) => Err(format!(
+            "unknown function '{}' with {} arguments",
+            name,
+            args.len()
+        )),
+    }
+}
+
+/// Comma separated arguments, either numbers or quoted ('' or "") strings
+fn parse_args(args: &str) -> Result<Vec<String>, String> {
+    let mut parsed = Vec::new();
+    let mut chars = args.trim().chars().peekable();
+    while chars.peek().is_some() {
+        let arg = match chars.peek() {
+            Some(&quote @ ('"' | '\'')) => {
+                chars.next();
+                let arg: String = chars.by_ref().take_while(|c| *c != quote).collect();
+                while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                    chars.next();
+                }
+                arg
+            }
+            _ => {
+                let mut arg = String::new();
+                while let Some(c) = chars.next_if(|c| *c != ',') {
+                    arg.push(c);
+                }
+                arg.trim().to_owned()
+            }
+        };
+        parsed.push(arg);
+        match chars.peek() {
+            Some(',') => {
+                chars.next();
+                while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                    chars.next();
+                }
+            }
+            Some(c) => return Err(format!("unexpected '{}' in arguments '{}'", c, args)),
+            None => {}
+        }
+    }
+    Ok(parsed)
+}
+
+fn int_arg(arg: &str) -> Result<i64, String> {
+    arg.parse()
+        .map_err(|_| format!("expected an integer argument, found '{}'", arg))
+}
+
+fn length_arg(arg: &str) -> Result<usize, String> {
+    let len = int_arg(arg)?;
+    if !(0..=MAX_STRING_LENGTH).contains(&len) {
+        return Err(format!(
+            "random_string: length {} is not between 0 and {}",
+            len, MAX_STRING_LENGTH
+        ));
+    }
+    Ok(len as usize)
+}
+
+fn random_string(rng: &mut impl Rng, len: usize) -> String {
+    rng.sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+fn next_in_sequence(name: &str, start: u64) -> u64 {
+    let counter = {
+        let mut sequences = SEQUENCES
+            .get_or_init(Default::default)
+            .lock()
+            .expect("sequences lock poisoned");
+        sequences
+            .entry(name.to_owned())
+            .or_insert_with(|| Arc::new(AtomicU64::new(start)))
+            .clone()
+    };
+    counter.fetch_add(1, Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::mock::StepRng;
+    use serde_json::json;
+
+    use super::{render, render_value, Variables};
+
+    #[test]
+    fn it_should_replace_variables() {
+        let variables = Variables::from([
+            ("order_id".to_owned(), "42".to_owned()),
+            ("account".to_owned(), "00042".to_owned()),
+        ]);
+        let mut rng = StepRng::new(0, 1);
+
+        assert_eq!(
+            "/orders/42/items",
+            render("/orders/{{ order_id }}/items", &variables, &mut rng).unwrap()
+        );
+        assert_eq!(
+            json!({"ids": ["42", 42], "account": "00042", "label": "id 42", "n": 1}),
+            render_value(
+                &json!({
+                    "ids": ["{{order_id}}", "{{ order_id | number }}"],
+                    "account": "{{ account }}",
+                    "label": "id {{order_id}}",
+                    "n": 1
+                }),
+                &variables,
+                &mut rng
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn it_should_fail_to_convert_other_values_to_numbers() {
+        let variables = Variables::from([("account".to_owned(), "00042".to_owned())]);
+        let mut rng = StepRng::new(0, 1);
+        let err = render_value(&json!("{{ account | number }}"), &variables, &mut rng);
+
+        assert!(err.unwrap_err().contains("00042"));
+    }
+
+    #[test]
+    fn it_should_fail_on_unknown_variables() {
+        let mut rng = StepRng::new(0, 1);
+        let err = render("/orders/{{ missing }}", &Variables::new(), &mut rng).unwrap_err();
+
+        assert!(err.contains("missing"));
+    }
+
+    #[test]
+    fn it_should_evaluate_functions() {
+        let variables = Variables::new();
+        let mut rng = rand::thread_rng();
+
+        let uuid = render("{{ uuid() }}", &variables, &mut rng).unwrap();
+        assert_eq!(36, uuid.len());
+
+        let n: i64 = render("{{ random_int(5, 7) }}", &variables, &mut rng)
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((5..=7).contains(&n));
+
+        assert_eq!(
+            12,
+            render("{{ random_string(12) }}", &variables, &mut rng)
+                .unwrap()
+                .len()
+        );
+
+        let picked = render("{{ pick('a, b', \"c\") }}", &variables, &mut rng).unwrap();
+        assert!(picked == "a, b" || picked == "c");
+
+        assert_eq!(
+            "100-101",
+            render(
+                "{{ sequence('test', 100) }}-{{ sequence('test') }}",
+                &variables,
+                &mut rng
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn it_should_reject_negative_or_huge_lengths() {
+        let mut rng = StepRng::new(0, 1);
+        let variables = Variables::new();
+
+        assert!(render("{{ random_string(-1) }}", &variables, &mut rng).is_err());
+        assert!(render("{{ random_string(-5, 3) }}", &variables, &mut rng).is_err());
+        assert!(render("{{ random_string(2000000) }}", &variables, &mut rng).is_err());
+        assert!(render("{{ sequence('x', -5) }}", &variables, &mut rng).is_err());
+    }
+
+    #[test]
+    fn it_should_fail_on_unknown_functions() {
+        let mut rng = StepRng::new(0, 1);
+        assert!(render("{{ nope(1) }}", &Variables::new(), &mut rng).is_err());
+        assert!(render("{{ random_int(1) }}", &Variables::new(), &mut rng).is_err());
+    }
+}
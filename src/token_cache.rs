@@ -0,0 +1,153 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+
+use crate::config::LoginParameters;
+use crate::login::LoginResponse;
+
+/// Cached tokens are discarded this many seconds before they actually expire,
+/// so they don't expire in the middle of a run.
+const EXPIRY_MARGIN_SECS: i64 = 60;
+
+/// Returns the cached token for these login parameters if it is still valid.
+pub(crate) fn load(params: &LoginParameters) -> Option<LoginResponse> {
+    load_from(&cache_file(params)?, chrono::Utc::now().timestamp())
+}
+
+fn load_from(path: &Path, now: i64) -> Option<LoginResponse> {
+    let content = fs::read_to_string(path).ok()?;
+    let login: LoginResponse = serde_json::from_str(&content).ok()?;
+    is_valid(&login, now).then_some(login)
+}
+
+/// Whether the token is still valid for `EXPIRY_MARGIN_SECS` after `now`
+fn is_valid(login: &LoginResponse, now: i64) -> bool {
+    login
+        .expires_at
+        .is_some_and(|expires_at| expires_at - EXPIRY_MARGIN_SECS > now)
+}
+
+/// Stores the token, readable only by the current user. Tokens without
+/// expiry are not stored since there is no way to know when to discard them.
+pub(crate) fn store(params: &LoginParameters, login: &LoginResponse) -> anyhow::Result<()> {
+    if login.expires_at.is_none() {
+        return Ok(());
+    }
+    let path = cache_file(params).context("no cache directory available")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("error creating {:?}", dir))?;
+    }
+    store_to(&path, login)
+}
+
+fn store_to(path: &Path, login: &LoginResponse) -> anyhow::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("error opening {:?}", path))?;
+    // the mode only applies to new files, an existing one keeps its own
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))
+            .with_context(|| format!("error restricting access to {:?}", path))?;
+    }
+    file.write_all(serde_json::to_string(login)?.as_bytes())
+        .with_context(|| format!("error writing {:?}", path))?;
+    Ok(())
+}
+
+fn cache_file(params: &LoginParameters) -> Option<PathBuf> {
+    let mut dir = dirs::cache_dir()?;
+    dir.push(env!("CARGO_PKG_NAME"));
+    dir.push("tokens");
+    dir.push(format!("{}.json", cache_key(params)));
+    Some(dir)
+}
+
+fn cache_key(params: &LoginParameters) -> String {
+    let mut hasher = Sha256::new();
+    for part in [&params.token_url, &params.client_id, &params.username] {
+        hasher.update(part.as_bytes());
+        hasher.update([0u8]);
+    }
+    hex::encode(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cache_key, is_valid, load_from, store_to, EXPIRY_MARGIN_SECS};
+    use crate::config::LoginParameters;
+    use crate::login::LoginResponse;
+
+    fn login_parameters(username: &str, password: &str) -> LoginParameters {
+        LoginParameters {
+            token_url: "https://auth.example.com/token".to_owned(),
+            client_id: "parreq".to_owned(),
+            client_secret: "secret".to_owned(),
+            username: username.to_owned(),
+            password: password.to_owned(),
+            grant_type: "password".to_owned(),
+        }
+    }
+
+    fn login_response(expires_at: Option<i64>) -> LoginResponse {
+        LoginResponse {
+            access_token: "token".to_owned(),
+            expires_in: Some(3600),
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn it_should_use_the_same_key_for_the_same_login_parameters() {
+        let key = cache_key(&login_parameters("alice", "first"));
+
+        // a new password still logs the same user in
+        assert_eq!(key, cache_key(&login_parameters("alice", "second")));
+        assert_ne!(key, cache_key(&login_parameters("bob", "first")));
+    }
+
+    #[test]
+    fn it_should_ignore_expired_or_nearly_expired_tokens() {
+        let now = 1_700_000_000;
+
+        assert!(is_valid(&login_response(Some(now + 3600)), now));
+        assert!(!is_valid(
+            &login_response(Some(now + EXPIRY_MARGIN_SECS)),
+            now
+        ));
+        assert!(!is_valid(&login_response(Some(now - 1)), now));
+        assert!(!is_valid(&login_response(None), now));
+    }
+
+    #[test]
+    fn it_should_read_back_a_stored_token() {
+        let path = std::env::temp_dir().join(format!("parreq-token-{}.json", std::process::id()));
+        let now = 1_700_000_000;
+
+        store_to(&path, &login_response(Some(now + 3600))).unwrap();
+        let loaded = load_from(&path, now);
+        let expired = load_from(&path, now + 3600);
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::metadata(&path).unwrap().permissions().mode() & 0o777
+        };
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!("token", loaded.unwrap().access_token);
+        assert!(expired.is_none());
+        #[cfg(unix)]
+        assert_eq!(0o600, mode);
+    }
+}
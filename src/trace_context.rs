@@ -0,0 +1,125 @@
+use opentelemetry::trace::TraceContextExt;
+use reqwest::header::{HeaderMap, HeaderValue};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::config::TraceContextParameters;
+
+/// W3C trace context headers added to the outgoing requests, so a request
+/// can be found among the server-side traces
+pub(crate) struct TraceContext {
+    run_id: String,
+    baggage: bool,
+}
+
+/// Ids sent in the `traceparent` header of a request
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TraceIds {
+    trace_id: [u8; 16],
+    parent_id: [u8; 8],
+    sampled: bool,
+}
+
+impl TraceContext {
+    pub(crate) fn new(params: &TraceContextParameters, run_id: &str) -> Self {
+        Self {
+            run_id: run_id.to_owned(),
+            baggage: params.baggage,
+        }
+    }
+
+    /// Ids of the request span when it is exported to an OpenTelemetry
+    /// collector, so the server spans show up as its children. Random ones
+    /// otherwise: they aren't derived from the seed, a run replayed with the
+    /// same seed must not reuse the trace ids.
+    pub(crate) fn ids(&self) -> TraceIds {
+        let context = tracing::Span::current().context();
+        let span_context = context.span().span_context().clone();
+        if span_context.is_valid() {
+            return TraceIds {
+                trace_id: span_context.trace_id().to_bytes(),
+                parent_id: span_context.span_id().to_bytes(),
+                sampled: span_context.is_sampled(),
+            };
+        }
+        TraceIds {
+            trace_id: rand::random(),
+            parent_id: rand::random(),
+            sampled: true,
+        }
+    }
+
+    pub(crate) fn add_headers(
+        &self,
+        headers: &mut HeaderMap,
+        ids: &TraceIds,
+        request_number: usize,
+    ) {
+        let traceparent = format!(
+            "00-{}-{}-{:02x}",
+            ids.trace_id(),
+            hex::encode(ids.parent_id),
+            u8::from(ids.sampled)
+        );
+        headers.insert(
+            "traceparent",
+            HeaderValue::try_from(traceparent).expect("hex digits only"),
+        );
+        if self.baggage {
+            let baggage = format!(
+                "parreq.run_id={},parreq.request_number={}",
+                self.run_id, request_number
+            );
+            headers.insert(
+                "baggage",
+                HeaderValue::try_from(baggage).expect("uuid and digits only"),
+            );
+        }
+    }
+}
+
+impl TraceIds {
+    pub(crate) fn trace_id(&self) -> String {
+        hex::encode(self.trace_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderMap;
+
+    use super::{TraceContext, TraceIds};
+    use crate::config::TraceContextParameters;
+
+    #[test]
+    fn it_should_add_traceparent_and_baggage() {
+        let context = TraceContext::new(&TraceContextParameters { baggage: true }, "run-1");
+        let ids = TraceIds {
+            trace_id: [0xab; 16],
+            parent_id: [0x01; 8],
+            sampled: true,
+        };
+        let mut headers = HeaderMap::new();
+        context.add_headers(&mut headers, &ids, 42);
+
+        assert_eq!(
+            "00-abababababababababababababababab-0101010101010101-01",
+            headers["traceparent"]
+        );
+        assert_eq!(
+            "parreq.run_id=run-1,parreq.request_number=42",
+            headers["baggage"]
+        );
+    }
+
+    #[test]
+    fn it_should_generate_ids_without_an_exported_span() {
+        let context = TraceContext::new(&TraceContextParameters { baggage: false }, "run-1");
+        let (first, second) = (context.ids(), context.ids());
+
+        assert_eq!(32, first.trace_id().len());
+        assert_ne!(first.trace_id(), second.trace_id());
+        let mut headers = HeaderMap::new();
+        context.add_headers(&mut headers, &first, 1);
+        assert!(!headers.contains_key("baggage"));
+    }
+}
@@ -3,22 +3,57 @@ use crossterm::{
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use std::{sync::mpsc::Receiver, time::Duration};
 use std::{error::Error, io, sync::RwLock};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Receiver,
+        Arc,
+    },
+    time::Duration,
+};
 use tui::{
     backend::{Backend, CrosstermBackend},
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
-    text::Span,
-    widgets::{Block, Borders, Gauge, Paragraph, Sparkline},
+    symbols,
+    text::{Span, Spans},
+    widgets::{
+        Axis, BarChart, Block, Borders, Cell, Chart, Dataset, Gauge, GraphType, Paragraph, Row,
+        Sparkline, Table, Tabs,
+    },
     Frame, Terminal,
 };
 
-use crate::metrics::{MetricsSummary, RequestMetric};
+use crate::metrics::{ComputedMetrics, EndpointColumn, MetricsSummary, RequestMetric};
+
+/// The tui is redrawn at this interval, however many requests complete
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Tabs of the tui, switched with tab
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum View {
+    Overview,
+    Endpoints,
+}
+
+/// What the keys changed since the tui started
+#[derive(Debug)]
+struct UiState {
+    view: View,
+    /// First error shown in the error log
+    error_scroll: usize,
+    sort_column: EndpointColumn,
+    sort_descending: bool,
+}
 
 pub(crate) fn run_ui(
     total_requests_expected: usize,
+    seed: u64,
+    pacing: Option<Duration>,
     metrics_receiver: &mut Receiver<RequestMetric>,
+    computed_metrics: Arc<ComputedMetrics>,
+    done: Arc<AtomicBool>,
 ) -> Result<(), Box<dyn Error>> {
     // setup terminal
     enable_raw_mode()?;
@@ -27,10 +62,17 @@ pub(crate) fn run_ui(
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let metrics_summary = RwLock::new(MetricsSummary::new(total_requests_expected));
+    let metrics_summary = RwLock::new(MetricsSummary::new(total_requests_expected, pacing));
 
     // create app and run it
-    let res = run_app(&mut terminal, metrics_summary, metrics_receiver);
+    let res = run_app(
+        &mut terminal,
+        seed,
+        metrics_summary,
+        metrics_receiver,
+        &computed_metrics,
+        &done,
+    );
 
     // restore terminal
     disable_raw_mode()?;
@@ -50,23 +92,60 @@ pub(crate) fn run_ui(
 
 fn run_app<B: Backend>(
     terminal: &mut Terminal<B>,
+    seed: u64,
     metrics_summary: RwLock<MetricsSummary>,
     metrics_receiver: &mut Receiver<RequestMetric>,
+    computed_metrics: &ComputedMetrics,
+    done: &AtomicBool,
 ) -> io::Result<()> {
-    while let Ok(request_metric) = metrics_receiver.recv() {
+    let mut state = UiState {
+        view: View::Overview,
+        error_scroll: 0,
+        sort_column: EndpointColumn::Name,
+        sort_descending: false,
+    };
+    loop {
+        // once the executors are done, this last drain gets all the metrics
+        let executors_done = done.load(Ordering::Acquire);
+        // read first: every request counted has its metric in the channel,
+        // unless dropped
+        let counts = computed_metrics.counts();
         let mut metrics_summary_mut = metrics_summary.write().unwrap();
-        metrics_summary_mut.record(request_metric);
+        metrics_receiver
+            .try_iter()
+            .for_each(|request_metric| metrics_summary_mut.record(request_metric));
+        metrics_summary_mut.set_counts(counts);
+        let last_frame = executors_done || metrics_summary_mut.is_completed();
+        metrics_summary_mut.update_corrected_latencies(tokio::time::Instant::now(), last_frame);
         drop(metrics_summary_mut);
         let metrics_summary = metrics_summary.read().unwrap();
-        terminal.draw(|f| ui(f, &metrics_summary))?;
-        if metrics_summary.is_completed() {
+        terminal.draw(|f| ui(f, seed, &metrics_summary, &state))?;
+        if last_frame {
             break;
         }
-        let timeout = Duration::from_millis(50);
-        if crossterm::event::poll(timeout)? {
+        if crossterm::event::poll(REFRESH_INTERVAL)? {
             if let Event::Key(key) = event::read()? {
-                if let KeyCode::Char('q') = key.code {
-                    return Ok(());
+                match (state.view, key.code) {
+                    (_, KeyCode::Char('q')) => return Ok(()),
+                    (View::Overview, KeyCode::Tab) => state.view = View::Endpoints,
+                    (View::Endpoints, KeyCode::Tab) => state.view = View::Overview,
+                    (View::Overview, KeyCode::Up) => {
+                        state.error_scroll = state.error_scroll.saturating_sub(1)
+                    }
+                    (View::Overview, KeyCode::Down) => {
+                        let last = metrics_summary.error_log.len().saturating_sub(1);
+                        state.error_scroll = (state.error_scroll + 1).min(last);
+                    }
+                    (View::Endpoints, KeyCode::Left) => {
+                        state.sort_column = state.sort_column.previous()
+                    }
+                    (View::Endpoints, KeyCode::Right) => {
+                        state.sort_column = state.sort_column.next()
+                    }
+                    (View::Endpoints, KeyCode::Char('r')) => {
+                        state.sort_descending = !state.sort_descending
+                    }
+                    _ => {}
                 }
             }
         }
@@ -74,34 +153,68 @@ fn run_app<B: Backend>(
     Ok(())
 }
 
-fn ui<B: Backend>(f: &mut Frame<B>, metrics: &MetricsSummary) {
-    let completed = metrics.ok + metrics.errors;
+fn ui<B: Backend>(f: &mut Frame<B>, seed: u64, metrics: &MetricsSummary, state: &UiState) {
+    let completed = metrics.completed();
     let total = metrics.total_expected;
     // main
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(2)
-        .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+        .constraints(
+            [
+                Constraint::Length(3),
+                Constraint::Length(1),
+                Constraint::Min(0),
+            ]
+            .as_ref(),
+        )
         .split(f.size());
 
     let label = format!("{}/{}", completed, total);
     let gauge = Gauge::default()
-        .block(Block::default().title("Progress ").borders(Borders::ALL))
+        .block(
+            Block::default()
+                .title(format!("Progress (seed {}) ", seed))
+                .borders(Borders::ALL),
+        )
         .gauge_style(Style::default().fg(Color::Cyan))
         .label(label)
         .percent((completed as f64 / total as f64 * 100f64) as u16);
     f.render_widget(gauge, chunks[0]);
 
+    let tabs = Tabs::new(vec![Spans::from("Overview"), Spans::from("Endpoints")])
+        .select(match state.view {
+            View::Overview => 0,
+            View::Endpoints => 1,
+        })
+        .highlight_style(
+            Style::default()
+                .add_modifier(Modifier::BOLD)
+                .fg(Color::Cyan),
+        );
+    f.render_widget(tabs, chunks[1]);
+    if state.view == View::Endpoints {
+        endpoints(f, metrics, state, chunks[2]);
+        return;
+    }
+
     // details
     let details_chunks = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([Constraint::Percentage(30), Constraint::Percentage(70)].as_ref())
-        .split(chunks[1]);
+        .split(chunks[2]);
 
     // detals left
     let left_details_chunks = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Min(7), Constraint::Min(3)].as_ref())
+        .constraints(
+            [
+                Constraint::Length(13),
+                Constraint::Length(8),
+                Constraint::Min(8),
+            ]
+            .as_ref(),
+        )
         .split(details_chunks[0]);
 
     let counts_block = Block::default()
@@ -112,7 +225,20 @@ fn ui<B: Backend>(f: &mut Frame<B>, metrics: &MetricsSummary) {
     let counts_chunks = Layout::default()
         .margin(2)
         .direction(Direction::Vertical)
-        .constraints([Constraint::Max(1), Constraint::Max(1), Constraint::Max(1)].as_ref())
+        .constraints(
+            [
+                Constraint::Max(1),
+                Constraint::Max(1),
+                Constraint::Max(1),
+                Constraint::Max(1),
+                Constraint::Max(1),
+                Constraint::Max(1),
+                Constraint::Max(1),
+                Constraint::Max(1),
+                Constraint::Max(1),
+            ]
+            .as_ref(),
+        )
         .split(left_details_chunks[0]);
 
     let in_progress_span = Span::styled(
@@ -131,11 +257,139 @@ fn ui<B: Backend>(f: &mut Frame<B>, metrics: &MetricsSummary) {
     );
     f.render_widget(Paragraph::new(ok_span), counts_chunks[1]);
 
+    let extraction_span = Span::styled(
+        format!("  Extraction: \t{} missed", metrics.extraction_misses),
+        Style::default().fg(Color::Yellow),
+    );
+    f.render_widget(Paragraph::new(extraction_span), counts_chunks[2]);
+
     let errors_span = Span::styled(
         format!("Errors      : \t{}", metrics.errors),
         Style::default().add_modifier(Modifier::BOLD).fg(Color::Red),
     );
-    f.render_widget(Paragraph::new(errors_span), counts_chunks[2]);
+    f.render_widget(Paragraph::new(errors_span), counts_chunks[3]);
+
+    let timeouts_span = Span::styled(
+        format!("  Timeouts  : \t{}", metrics.timeouts),
+        Style::default().fg(Color::Red),
+    );
+    f.render_widget(Paragraph::new(timeouts_span), counts_chunks[4]);
+
+    let first_attempt_span = Span::styled(
+        format!(
+            "1st attempt : \t{} failed ({} retries)",
+            metrics.first_attempt_errors, metrics.retries
+        ),
+        Style::default().fg(Color::Yellow),
+    );
+    f.render_widget(Paragraph::new(first_attempt_span), counts_chunks[5]);
+
+    let warmup_span = Span::styled(
+        format!("Warm-up     : \t{} (not counted)", metrics.warmup),
+        Style::default().fg(Color::DarkGray),
+    );
+    f.render_widget(Paragraph::new(warmup_span), counts_chunks[6]);
+
+    let skipped_span = Span::styled(
+        format!("Skipped     : \t{} (previous step failed)", metrics.skipped),
+        Style::default().fg(Color::DarkGray),
+    );
+    f.render_widget(Paragraph::new(skipped_span), counts_chunks[7]);
+
+    let overhead_span = Span::styled(
+        format!(
+            "Metrics     : \t{} µs/request, {} dropped",
+            metrics.overhead_per_request.as_micros(),
+            metrics.dropped
+        ),
+        Style::default().fg(Color::DarkGray),
+    );
+    f.render_widget(Paragraph::new(overhead_span), counts_chunks[8]);
+
+    let corrected = metrics.corrected_latencies_us();
+    let now = tokio::time::Instant::now();
+    let window = metrics.latency_window.latencies_us(now);
+    let header = Row::new(vec![
+        "ms".to_owned(),
+        "Raw".to_owned(),
+        "Corrected".to_owned(),
+        format!("Last {}s", metrics.latency_window.window().as_secs()),
+    ])
+    .style(Style::default().add_modifier(Modifier::BOLD));
+    let latency_row = |label: &'static str, raw: u64, corrected: u64, window: u64| {
+        Row::new(vec![
+            Cell::from(label),
+            Cell::from(format!("{:.1}", raw as f64 / 1000f64)),
+            Cell::from(format!("{:.1}", corrected as f64 / 1000f64))
+                .style(Style::default().fg(Color::Yellow)),
+            Cell::from(format!("{:.1}", window as f64 / 1000f64))
+                .style(Style::default().fg(Color::Cyan)),
+        ])
+    };
+    let mut latency_rows: Vec<_> = [("p50", 0.5), ("p90", 0.9), ("p99", 0.99), ("p99.9", 0.999)]
+        .into_iter()
+        .map(|(label, quantile)| {
+            latency_row(
+                label,
+                metrics.latencies_us.value_at_quantile(quantile),
+                corrected.value_at_quantile(quantile),
+                window.value_at_quantile(quantile),
+            )
+        })
+        .collect();
+    latency_rows.push(latency_row(
+        "max",
+        metrics.latencies_us.max(),
+        corrected.max(),
+        window.max(),
+    ));
+    // corrected for the requests an executor couldn't send while waiting
+    let latency_title = format!(
+        "Latency (expected interval {:.1} ms)",
+        metrics.expected_interval_us() as f64 / 1000f64
+    );
+    let latency_table = Table::new(latency_rows)
+        .header(header)
+        .block(Block::default().title(latency_title).borders(Borders::ALL))
+        .widths(&[
+            Constraint::Percentage(22),
+            Constraint::Percentage(26),
+            Constraint::Percentage(26),
+            Constraint::Percentage(26),
+        ]);
+    f.render_widget(latency_table, left_details_chunks[1]);
+
+    // as many bars as the panel fits, labeled with their upper bound
+    let bar_width = 5;
+    let bars = (left_details_chunks[2].width.saturating_sub(2) / (bar_width + 1)).max(1);
+    let distribution: Vec<_> = metrics
+        .latency_distribution(bars as usize)
+        .into_iter()
+        .map(|(upper_us, count)| {
+            let upper_ms = upper_us as f64 / 1000f64;
+            let label = if upper_ms < 100f64 {
+                format!("{:.1}", upper_ms)
+            } else {
+                format!("{:.0}", upper_ms)
+            };
+            (label, count)
+        })
+        .collect();
+    let distribution: Vec<_> = distribution
+        .iter()
+        .map(|(label, count)| (label.as_str(), *count))
+        .collect();
+    let distribution_chart = BarChart::default()
+        .block(
+            Block::default()
+                .title("Latency distribution up to p99 (ms)")
+                .borders(Borders::ALL),
+        )
+        .data(&distribution)
+        .bar_width(bar_width)
+        .bar_style(Style::default().fg(Color::Cyan))
+        .value_style(Style::default().fg(Color::Black).bg(Color::Cyan));
+    f.render_widget(distribution_chart, left_details_chunks[2]);
 
     // details rigth
     // detals left
@@ -145,14 +399,15 @@ fn ui<B: Backend>(f: &mut Frame<B>, metrics: &MetricsSummary) {
             [
                 Constraint::Length(4),
                 Constraint::Length(4),
-                Constraint::Min(1),
+                Constraint::Length(12),
+                Constraint::Min(10),
             ]
             .as_ref(),
         )
         .split(details_chunks[1]);
 
     let width = right_details_chunks[0].width;
-    let rel: usize = total / width as usize;
+    let rel: usize = (total / width as usize).max(1);
 
     let ok_durations: Vec<_> = metrics
         .ok_durations_ms
@@ -189,4 +444,160 @@ fn ui<B: Backend>(f: &mut Frame<B>, metrics: &MetricsSummary) {
         .data(&error_durations)
         .style(Style::default().fg(Color::Red));
     f.render_widget(error_durations_sparkline, right_details_chunks[1]);
+
+    let throughput = &metrics.throughput;
+    let completed_rps = throughput.completed_points(now);
+    let error_rps = throughput.error_points(now);
+    let elapsed = completed_rps
+        .last()
+        .map_or(0f64, |(second, _)| *second)
+        .max(1f64);
+    let peak = throughput.peak_rps(now).max(1) as f64;
+    let throughput_title = format!(
+        "Throughput (current {}/s, avg {:.1}/s, peak {}/s)",
+        throughput.current_rps(now),
+        throughput.average_rps(now),
+        throughput.peak_rps(now)
+    );
+    let throughput_chart = Chart::new(vec![
+        Dataset::default()
+            .name("requests/s")
+            .marker(symbols::Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(Color::Cyan))
+            .data(&completed_rps),
+        Dataset::default()
+            .name("errors/s")
+            .marker(symbols::Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(Color::Red))
+            .data(&error_rps),
+    ])
+    .block(
+        Block::default()
+            .title(throughput_title)
+            .borders(Borders::ALL),
+    )
+    .x_axis(
+        Axis::default()
+            .style(Style::default().fg(Color::DarkGray))
+            .bounds([0f64, elapsed])
+            .labels(vec![Span::from("0s"), Span::from(format!("{}s", elapsed))]),
+    )
+    .y_axis(
+        Axis::default()
+            .style(Style::default().fg(Color::DarkGray))
+            .bounds([0f64, peak])
+            .labels(vec![Span::from("0"), Span::from(format!("{}", peak))]),
+    );
+    f.render_widget(throughput_chart, right_details_chunks[2]);
+
+    let header = Row::new(vec![
+        "Time", "Request", "Url", "Status", "Count", "Error", "Trace id",
+    ])
+    .style(Style::default().add_modifier(Modifier::BOLD));
+    let errors = metrics.error_log.entries();
+    let rows: Vec<_> = errors
+        .iter()
+        .skip(state.error_scroll)
+        .map(|error| {
+            Row::new(vec![
+                Cell::from(error.last_seen.format("%H:%M:%S").to_string()),
+                Cell::from(error.name.as_str()),
+                Cell::from(error.url.as_str()),
+                Cell::from(
+                    error
+                        .status_code
+                        .map_or_else(|| "-".to_owned(), |status| status.to_string()),
+                ),
+                Cell::from(error.count.to_string()).style(Style::default().fg(Color::Red)),
+                Cell::from(error.message.as_str()),
+                Cell::from(error.trace_id.as_deref().unwrap_or("-")),
+            ])
+        })
+        .collect();
+    let error_log_title = format!(
+        "Error log ({} distinct, {}/{}, up/down to scroll)",
+        errors.len(),
+        (state.error_scroll + 1).min(errors.len()),
+        errors.len()
+    );
+    let error_log_table = Table::new(rows)
+        .header(header)
+        .block(
+            Block::default()
+                .title(error_log_title)
+                .borders(Borders::ALL),
+        )
+        .widths(&[
+            Constraint::Percentage(7),
+            Constraint::Percentage(12),
+            Constraint::Percentage(17),
+            Constraint::Percentage(5),
+            Constraint::Percentage(5),
+            Constraint::Percentage(22),
+            // a whole trace id
+            Constraint::Length(32),
+        ]);
+    f.render_widget(error_log_table, right_details_chunks[3]);
+}
+
+/// One row per request name, sorted with the keys
+fn endpoints<B: Backend>(f: &mut Frame<B>, metrics: &MetricsSummary, state: &UiState, area: Rect) {
+    let header = Row::new(EndpointColumn::ALL.map(|column| {
+        let title = column.title();
+        if column != state.sort_column {
+            Cell::from(title)
+        } else if state.sort_descending {
+            Cell::from(format!("{} ▼", title)).style(Style::default().fg(Color::Cyan))
+        } else {
+            Cell::from(format!("{} ▲", title)).style(Style::default().fg(Color::Cyan))
+        }
+    }))
+    .style(Style::default().add_modifier(Modifier::BOLD));
+    let ms = |us: u64| format!("{:.1}", us as f64 / 1000f64);
+    let rows: Vec<_> = metrics
+        .endpoints(state.sort_column, state.sort_descending)
+        .into_iter()
+        .map(|row| {
+            let error_style = if row.error_rate > 0f64 {
+                Style::default().fg(Color::Red)
+            } else {
+                Style::default()
+            };
+            Row::new(vec![
+                Cell::from(row.name),
+                Cell::from(row.count.to_string()),
+                Cell::from(format!("{:.1}%", row.share)),
+                Cell::from(format!("{:.1}", row.rps)),
+                Cell::from(format!("{:.1}%", row.error_rate)).style(error_style),
+                Cell::from(ms(row.p50_us)),
+                Cell::from(ms(row.p95_us)),
+                Cell::from(ms(row.p99_us)).style(Style::default().fg(Color::Yellow)),
+                Cell::from(
+                    row.last_status
+                        .map_or_else(|| "-".to_owned(), |status| status.to_string()),
+                ),
+            ])
+        })
+        .collect();
+    let endpoints_table = Table::new(rows)
+        .header(header)
+        .block(
+            Block::default()
+                .title("Endpoints (left/right to sort, r to reverse)")
+                .borders(Borders::ALL),
+        )
+        .widths(&[
+            Constraint::Percentage(28),
+            Constraint::Percentage(9),
+            Constraint::Percentage(9),
+            Constraint::Percentage(9),
+            Constraint::Percentage(9),
+            Constraint::Percentage(9),
+            Constraint::Percentage(9),
+            Constraint::Percentage(9),
+            Constraint::Percentage(9),
+        ]);
+    f.render_widget(endpoints_table, area);
 }
@@ -0,0 +1,62 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+use tokio::time::Instant;
+
+use crate::config::Warmup;
+
+/// Tells the requests sent during the warm-up, shared by all the executors
+#[derive(Debug)]
+pub(crate) struct WarmupPhase {
+    warmup: Warmup,
+    /// Time of the first request
+    start: OnceLock<Instant>,
+    started_requests: AtomicUsize,
+}
+
+impl WarmupPhase {
+    pub(crate) fn new(warmup: Warmup) -> Self {
+        Self {
+            warmup,
+            start: OnceLock::new(),
+            started_requests: AtomicUsize::new(0),
+        }
+    }
+
+    /// To be called once by each request, right before it is sent
+    pub(crate) fn is_warmup(&self) -> bool {
+        let start = *self.start.get_or_init(Instant::now);
+        match self.warmup {
+            Warmup::Requests(requests) => {
+                self.started_requests.fetch_add(1, Ordering::Relaxed) < requests
+            }
+            Warmup::Duration(duration) => start.elapsed() < duration,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::WarmupPhase;
+    use crate::config::Warmup;
+
+    #[test]
+    fn it_should_count_warmup_requests() {
+        let phase = WarmupPhase::new(Warmup::Requests(2));
+
+        let warmups: Vec<_> = (0..4).map(|_| phase.is_warmup()).collect();
+
+        assert_eq!(vec![true, true, false, false], warmups);
+    }
+
+    #[test]
+    fn it_should_end_warmup_after_duration() {
+        let phase = WarmupPhase::new(Warmup::Duration(Duration::from_millis(50)));
+
+        assert!(phase.is_warmup());
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(!phase.is_warmup());
+    }
+}